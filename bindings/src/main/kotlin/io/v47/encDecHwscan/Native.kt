@@ -19,23 +19,35 @@ package io.v47.encDecHwscan
 import fr.stardustenterprises.yanl.NativeLoader
 import io.v47.encDecHwscan.bindings.EncDecDevices
 import io.v47.encDecHwscan.bindings.EncDecHwscan
+import io.v47.encDecHwscan.bindings.Telemetry
+import org.jboss.logging.Logger
 import java.lang.foreign.Arena
 import java.lang.foreign.FunctionDescriptor
 import java.lang.foreign.Linker
 import java.lang.foreign.MemorySegment
 import java.lang.foreign.SymbolLookup
 import java.lang.foreign.ValueLayout
+import java.lang.invoke.MethodHandles
+import java.lang.invoke.MethodType
 
 /**
  * Entrypoint for accessing the native library via the Java Foreign Function and Memory API.
  */
 object Native {
-    private val SCAN_DEVICES_HANDLE by lazy {
+    /**
+     * Describes the `LogCallback` function pointer passed to `set_log_callback`.
+     */
+    val LOG_CALLBACK_DESCRIPTOR: FunctionDescriptor =
+        FunctionDescriptor.ofVoid(EncDecHwscan.C_INT, EncDecHwscan.C_POINTER, EncDecHwscan.C_POINTER)
+
+    private const val LOGGER_PREFIX = "io.v47.encDecHwscan.native"
+
+    private val SCAN_DEVICES_WITH_OPTIONS_HANDLE by lazy {
         Linker
             .nativeLinker()
             .downcallHandle(
-                SymbolLookup.loaderLookup().find("scan_devices").orElseThrow(),
-                FunctionDescriptor.of(EncDecHwscan.C_INT, EncDecHwscan.C_POINTER)
+                SymbolLookup.loaderLookup().find("scan_devices_with_options").orElseThrow(),
+                FunctionDescriptor.of(EncDecHwscan.C_INT, EncDecHwscan.C_POINTER, EncDecHwscan.C_POINTER)
             )
     }
 
@@ -48,25 +60,107 @@ object Native {
             )
     }
 
+    private val GET_DEVICE_TELEMETRY_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("get_device_telemetry").orElseThrow(),
+                FunctionDescriptor.of(EncDecHwscan.C_INT, EncDecHwscan.C_POINTER, EncDecHwscan.C_POINTER)
+            )
+    }
+
+    private val GET_LAST_ERROR_MESSAGE_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("get_last_error_message").orElseThrow(),
+                FunctionDescriptor.of(EncDecHwscan.C_POINTER)
+            )
+    }
+
+    private val GET_LAST_ERROR_NATIVE_CODE_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("get_last_error_native_code").orElseThrow(),
+                FunctionDescriptor.of(EncDecHwscan.C_LONG)
+            )
+    }
+
+    private val GET_LAST_ERROR_BACKEND_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("get_last_error_backend").orElseThrow(),
+                FunctionDescriptor.of(EncDecHwscan.C_POINTER)
+            )
+    }
+
+    private val SET_LOG_CALLBACK_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("set_log_callback").orElseThrow(),
+                FunctionDescriptor.ofVoid(EncDecHwscan.C_POINTER)
+            )
+    }
+
+    private val SET_LOG_LEVEL_HANDLE by lazy {
+        Linker
+            .nativeLinker()
+            .downcallHandle(
+                SymbolLookup.loaderLookup().find("set_log_level").orElseThrow(),
+                FunctionDescriptor.ofVoid(EncDecHwscan.C_INT)
+            )
+    }
+
+    private val LOG_CALLBACK_STUB by lazy {
+        Linker
+            .nativeLinker()
+            .upcallStub(
+                MethodHandles.lookup().findStatic(
+                    Native::class.java,
+                    "log",
+                    MethodType.methodType(
+                        Void.TYPE,
+                        Int::class.java,
+                        MemorySegment::class.java,
+                        MemorySegment::class.java
+                    )
+                ),
+                LOG_CALLBACK_DESCRIPTOR,
+                Arena.global()
+            )
+    }
+
     /**
-     * Loads the native library.
+     * Loads the native library and routes its log messages to JBoss Logging.
      */
     fun load() {
         NativeLoader
             .Builder().build()
             .loadLibrary("enc_dec_hwscan")
+
+        SET_LOG_LEVEL_HANDLE.invokeExact(nativeLogLevel(Logger.getLogger(LOGGER_PREFIX))) as Unit
+        SET_LOG_CALLBACK_HANDLE.invokeExact(LOG_CALLBACK_STUB) as Unit
     }
 
     /**
      * Calls the native library and takes care of memory allocation and clean-up.
+     *
+     * [options] allocates the `ScanOptions` passed to the native library, by default everything is
+     * scanned.
      */
-    fun <T : Any> scanDevices(mapper: (MemorySegment) -> T) =
+    fun <T : Any> scanDevices(
+        options: (Arena) -> MemorySegment = { MemorySegment.NULL },
+        mapper: (MemorySegment) -> T
+    ) =
         Arena.ofConfined().use { arena ->
             var supportInfo: MemorySegment? = null
 
             try {
                 val target = arena.allocate(ValueLayout.ADDRESS.withoutTargetLayout())
-                val errno = SCAN_DEVICES_HANDLE.invokeExact(target) as Int
+                val errno = SCAN_DEVICES_WITH_OPTIONS_HANDLE.invokeExact(options(arena), target) as Int
 
                 if (errno == 0) {
                     supportInfo = target
@@ -80,4 +174,70 @@ object Native {
                 supportInfo?.let { FREE_DEVICES_HANDLE.invokeExact(it) as Unit }
             }
         }
+
+    /**
+     * Queries the current telemetry of a CUDA device without scanning its capabilities again.
+     *
+     * [cudaDeviceInfo] allocates the `CudaDeviceInfo` identifying the device, [mapper] receives the
+     * `Telemetry` written by the native library.
+     */
+    fun <T : Any> deviceTelemetry(
+        cudaDeviceInfo: (Arena) -> MemorySegment,
+        mapper: (MemorySegment) -> T
+    ) =
+        Arena.ofConfined().use { arena ->
+            val target = arena.allocate(Telemetry.layout())
+            val errno = GET_DEVICE_TELEMETRY_HANDLE.invokeExact(cudaDeviceInfo(arena), target) as Int
+
+            if (errno == 0)
+                mapper(target) to 0
+            else
+                null to errno
+        }
+
+    /**
+     * Returns the message of the last error that occurred in the native library on the calling
+     * thread, the returned segment is `NULL` if there is none.
+     */
+    fun lastErrorMessage() = GET_LAST_ERROR_MESSAGE_HANDLE.invokeExact() as MemorySegment
+
+    /**
+     * Returns the driver status code of the last error that occurred in the native library on the
+     * calling thread, `0` if there is none.
+     */
+    fun lastErrorNativeCode() = GET_LAST_ERROR_NATIVE_CODE_HANDLE.invokeExact() as Long
+
+    /**
+     * Returns the backend of the last error that occurred in the native library on the calling
+     * thread, the returned segment is `NULL` if there is none.
+     */
+    fun lastErrorBackend() = GET_LAST_ERROR_BACKEND_HANDLE.invokeExact() as MemorySegment
+
+    @JvmStatic
+    private fun log(level: Int, target: MemorySegment, message: MemorySegment) {
+        val targetString = target.reinterpret(Long.MAX_VALUE).getString(0L)
+        val messageString = message.reinterpret(Long.MAX_VALUE).getString(0L)
+
+        Logger
+            .getLogger("$LOGGER_PREFIX.${targetString.replace("::", ".")}")
+            .log(
+                when (level) {
+                    EncDecHwscan.Error() -> Logger.Level.ERROR
+                    EncDecHwscan.Warn() -> Logger.Level.WARN
+                    EncDecHwscan.Info() -> Logger.Level.INFO
+                    EncDecHwscan.Debug() -> Logger.Level.DEBUG
+                    else -> Logger.Level.TRACE
+                },
+                messageString
+            )
+    }
+
+    private fun nativeLogLevel(logger: Logger) =
+        when {
+            logger.isTraceEnabled -> EncDecHwscan.Trace()
+            logger.isDebugEnabled -> EncDecHwscan.Debug()
+            logger.isInfoEnabled -> EncDecHwscan.Info()
+            logger.isEnabled(Logger.Level.WARN) -> EncDecHwscan.Warn()
+            else -> EncDecHwscan.Error()
+        }
 }
@@ -21,6 +21,8 @@ import io.v47.encDecHwscan.bindings.EncDecHwscan
 import org.graalvm.nativeimage.hosted.Feature
 import org.graalvm.nativeimage.hosted.Feature.DuringSetupAccess
 import org.graalvm.nativeimage.hosted.RuntimeForeignAccess
+import org.graalvm.nativeimage.hosted.RuntimeReflection
+import java.lang.foreign.MemorySegment
 
 @Suppress("unused")
 internal class EncDecHwscanFeature : Feature {
@@ -28,6 +30,24 @@ internal class EncDecHwscanFeature : Feature {
         Native.load()
 
         RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`scan_devices$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`scan_devices_with_options$descriptor`())
         RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`free_devices$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`get_device_telemetry$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`get_last_error_message$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`get_last_error_native_code$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`get_last_error_backend$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`set_log_callback$descriptor`())
+        RuntimeForeignAccess.registerForDowncall(EncDecHwscan.`set_log_level$descriptor`())
+        RuntimeForeignAccess.registerForUpcall(Native.LOG_CALLBACK_DESCRIPTOR)
+
+        // the log callback's target is looked up reflectively when creating the upcall stub
+        RuntimeReflection.register(
+            Native::class.java.getDeclaredMethod(
+                "log",
+                Int::class.java,
+                MemorySegment::class.java,
+                MemorySegment::class.java
+            )
+        )
     }
 }
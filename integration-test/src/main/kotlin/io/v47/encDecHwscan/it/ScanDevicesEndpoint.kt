@@ -18,7 +18,8 @@ package io.v47.encDecHwscan.it
 
 import io.quarkus.runtime.annotations.RegisterForReflection
 import io.v47.encDecHwscan.model.Device
-import io.v47.encDecHwscan.scanDevices
+import io.v47.encDecHwscan.model.Diagnostic
+import io.v47.encDecHwscan.scan
 import jakarta.ws.rs.GET
 import jakarta.ws.rs.Path
 import jakarta.ws.rs.Produces
@@ -27,12 +28,12 @@ import jakarta.ws.rs.core.MediaType
 @Path("/devices")
 @Produces(MediaType.APPLICATION_JSON)
 class ScanDevicesEndpoint {
-    val devices by lazy { scanDevices() }
+    val result by lazy { scan() }
 
     @GET
     fun getDevices() =
-        ScannedDevices(devices)
+        ScannedDevices(result.devices, result.diagnostics)
 }
 
 @RegisterForReflection
-data class ScannedDevices(val devices: List<Device>)
+data class ScannedDevices(val devices: List<Device>, val diagnostics: List<Diagnostic>)
@@ -20,41 +20,193 @@ import io.v47.encDecHwscan.bindings.EncDecHwscan
 import io.v47.encDecHwscan.exceptions.ConversionFailedException
 import io.v47.encDecHwscan.exceptions.CriticalErrorException
 import io.v47.encDecHwscan.exceptions.DriverFailureException
+import io.v47.encDecHwscan.exceptions.EncDecHwscanException
 import io.v47.encDecHwscan.exceptions.OperationFailedException
 import io.v47.encDecHwscan.exceptions.UnrecognizedErrorException
+import io.v47.encDecHwscan.model.ApiVersion
 import io.v47.encDecHwscan.model.Chroma
 import io.v47.encDecHwscan.model.Codec
 import io.v47.encDecHwscan.model.CodecDetails
 import io.v47.encDecHwscan.model.ColorDepth
+import io.v47.encDecHwscan.model.CudaDeviceInfo
 import io.v47.encDecHwscan.model.DecodingSpec
 import io.v47.encDecHwscan.model.Device
+import io.v47.encDecHwscan.model.Diagnostic
 import io.v47.encDecHwscan.model.Driver
+import io.v47.encDecHwscan.model.EncodePreset
 import io.v47.encDecHwscan.model.EncodeProfile
+import io.v47.encDecHwscan.model.EncoderStatus
 import io.v47.encDecHwscan.model.EncodingSpec
+import io.v47.encDecHwscan.model.ErrorKind
+import io.v47.encDecHwscan.model.LibraryInfo
+import io.v47.encDecHwscan.model.MultiPass
+import io.v47.encDecHwscan.model.NvencCaps
+import io.v47.encDecHwscan.model.NvencPreset
+import io.v47.encDecHwscan.model.OpticalFlowCaps
+import io.v47.encDecHwscan.model.OutputFormat
+import io.v47.encDecHwscan.model.PciAddress
+import io.v47.encDecHwscan.model.PixelFormat
+import io.v47.encDecHwscan.model.PresetConfig
+import io.v47.encDecHwscan.model.RateControlMode
+import io.v47.encDecHwscan.model.ScanOptions
+import io.v47.encDecHwscan.model.ScanResult
+import io.v47.encDecHwscan.model.Telemetry
+import io.v47.encDecHwscan.model.TuningInfo
+import java.lang.foreign.Arena
 import java.lang.foreign.MemorySegment
 import java.lang.foreign.ValueLayout
+import java.nio.ByteBuffer
+import java.nio.file.Path
+import java.util.UUID
 import kotlin.io.path.Path
+import io.v47.encDecHwscan.bindings.ApiVersion as RsApiVersion
 import io.v47.encDecHwscan.bindings.CodecDetails as RsCodecDetails
+import io.v47.encDecHwscan.bindings.CudaDeviceInfo as RsCudaDeviceInfo
 import io.v47.encDecHwscan.bindings.DecodingSpec as RsDecodingSpec
 import io.v47.encDecHwscan.bindings.Device as RsDevice
+import io.v47.encDecHwscan.bindings.Diagnostic as RsDiagnostic
 import io.v47.encDecHwscan.bindings.EncDecDevices as RsEncDecDevices
 import io.v47.encDecHwscan.bindings.EncodingSpec as RsEncodingSpec
+import io.v47.encDecHwscan.bindings.LibraryInfo as RsLibraryInfo
+import io.v47.encDecHwscan.bindings.NvencCapValue as RsNvencCapValue
+import io.v47.encDecHwscan.bindings.NvencCaps as RsNvencCaps
+import io.v47.encDecHwscan.bindings.NvencPreset as RsNvencPreset
+import io.v47.encDecHwscan.bindings.OpticalFlowCaps as RsOpticalFlowCaps
+import io.v47.encDecHwscan.bindings.PciAddress as RsPciAddress
+import io.v47.encDecHwscan.bindings.PresetConfig as RsPresetConfig
+import io.v47.encDecHwscan.bindings.ScanOptions as RsScanOptions
+import io.v47.encDecHwscan.bindings.Telemetry as RsTelemetry
 
 /**
  * Returns a list of devices and their capabilities.
+ *
+ * Devices that couldn't be scanned are omitted, use [scan] to find out why.
+ */
+fun scanDevices(): List<Device> = scan().devices
+
+/**
+ * Returns all devices that were scanned successfully along with diagnostics for all backends and
+ * devices that failed to scan.
+ *
+ * Use [options] to only probe a subset of the drivers, devices and codecs, which is considerably
+ * faster than a full scan.
+ *
+ * Throws an [EncDecHwscanException] describing the last failure if no backend succeeded and at least
+ * one of them failed, a machine without any available backend results in an empty [ScanResult].
  */
-fun scanDevices(): List<Device> {
+fun scan(options: ScanOptions = ScanOptions()): ScanResult {
     val (result, errno) =
-        Native.scanDevices { memorySegment ->
-            mapDevices(
-                RsEncDecDevices.devices(memorySegment),
-                RsEncDecDevices.num_devices(memorySegment)
+        Native.scanDevices({ arena -> allocateScanOptions(options, arena) }) { memorySegment ->
+            ScanResult(
+                mapDevices(
+                    RsEncDecDevices.devices(memorySegment),
+                    RsEncDecDevices.num_devices(memorySegment)
+                ),
+                mapDiagnostics(
+                    RsEncDecDevices.diagnostics(memorySegment),
+                    RsEncDecDevices.num_diagnostics(memorySegment)
+                ),
+                mapLibraries(
+                    RsEncDecDevices.libraries(memorySegment),
+                    RsEncDecDevices.num_libraries(memorySegment)
+                )
             )
         }
 
     return result ?: throw mapException(errno)
 }
 
+/**
+ * Returns [device] with its [Device.telemetry] queried again, without scanning its capabilities.
+ *
+ * Only CUDA devices report telemetry, and only if NVML is installed. The telemetry is `null`
+ * otherwise.
+ */
+fun refreshTelemetry(device: Device): Device {
+    val cuda = device.cuda ?: return device.copy(telemetry = null)
+
+    val (telemetry, errno) =
+        Native.deviceTelemetry({ arena -> allocateCudaDeviceInfo(cuda, arena) }) { mapTelemetry(it) }
+
+    return when (errno) {
+        0, EncDecHwscan.DriverFailure() -> device.copy(telemetry = telemetry)
+        else -> throw mapException(errno)
+    }
+}
+
+private fun allocateCudaDeviceInfo(cuda: CudaDeviceInfo, arena: Arena): MemorySegment {
+    val segment = RsCudaDeviceInfo.allocate(arena)
+
+    cuda.uuid?.let { uuid ->
+        val bytes = ByteBuffer
+            .allocate(UUID_SIZE)
+            .putLong(uuid.mostSignificantBits)
+            .putLong(uuid.leastSignificantBits)
+            .array()
+
+        MemorySegment.copy(bytes, 0, RsCudaDeviceInfo.uuid(segment), ValueLayout.JAVA_BYTE, 0L, UUID_SIZE)
+    }
+
+    val pciAddress = RsCudaDeviceInfo.pci_address(segment)
+    RsPciAddress.domain(pciAddress, cuda.pciAddress.domain)
+    RsPciAddress.bus(pciAddress, cuda.pciAddress.bus.toByte())
+    RsPciAddress.device(pciAddress, cuda.pciAddress.device.toByte())
+    RsPciAddress.function(pciAddress, cuda.pciAddress.function.toByte())
+
+    return segment
+}
+
+private fun allocateScanOptions(options: ScanOptions, arena: Arena): MemorySegment {
+    val segment = RsScanOptions.allocate(arena)
+
+    RsScanOptions.size(segment, RsScanOptions.sizeof().toInt())
+    RsScanOptions.disable_nvidia(segment, !options.nvidia)
+    RsScanOptions.disable_vaapi(segment, !options.vaapi)
+    RsScanOptions.probe_encoder_sessions(segment, options.probeEncoderSessions)
+    RsScanOptions.use_primary_context(segment, options.usePrimaryContext)
+
+    options.cudaOrdinals?.takeIf { it.isNotEmpty() }?.let { ordinals ->
+        RsScanOptions.cuda_ordinals(segment, arena.allocateFrom(ValueLayout.JAVA_BYTE, *ordinals.toByteArray()))
+        RsScanOptions.num_cuda_ordinals(segment, ordinals.size)
+    }
+
+    options.drmPaths?.takeIf { it.isNotEmpty() }?.let { paths ->
+        RsScanOptions.drm_paths(segment, allocatePaths(paths, arena))
+        RsScanOptions.num_drm_paths(segment, paths.size)
+    }
+
+    options.nvidiaLibraryDirs.takeIf { it.isNotEmpty() }?.let { dirs ->
+        RsScanOptions.nvidia_library_dirs(segment, allocatePaths(dirs, arena))
+        RsScanOptions.num_nvidia_library_dirs(segment, dirs.size)
+    }
+
+    options.nvidiaDeviceMask?.takeIf { it.isNotEmpty() }?.let { mask ->
+        RsScanOptions.nvidia_device_mask(segment, allocateStrings(mask, arena))
+        RsScanOptions.num_nvidia_device_mask(segment, mask.size)
+    }
+
+    options.codecs?.takeIf { it.isNotEmpty() }?.let { codecs ->
+        val codecValues = codecs.map { it.nativeValue }.toIntArray()
+
+        RsScanOptions.codecs(segment, arena.allocateFrom(ValueLayout.JAVA_INT, *codecValues))
+        RsScanOptions.num_codecs(segment, codecs.size)
+    }
+
+    return segment
+}
+
+private fun allocatePaths(paths: Collection<Path>, arena: Arena) =
+    allocateStrings(paths.map { it.toString() }, arena)
+
+private fun allocateStrings(strings: Collection<String>, arena: Arena): MemorySegment {
+    val stringsSegment = arena.allocate(ValueLayout.ADDRESS, strings.size.toLong())
+    strings.forEachIndexed { i, string ->
+        stringsSegment.setAtIndex(ValueLayout.ADDRESS, i.toLong(), arena.allocateFrom(string))
+    }
+
+    return stringsSegment
+}
+
 private fun mapDevices(devices: MemorySegment, numDevices: Int) =
     (0 until numDevices)
         .asSequence()
@@ -68,11 +220,102 @@ private fun mapDevices(devices: MemorySegment, numDevices: Int) =
                 mapCodecDetails(
                     RsDevice.codecs(device),
                     RsDevice.num_codecs(device)
-                )
+                ),
+                mapApiVersion(RsDevice.nvenc_api_version(device))
+                    .takeUnless { it.major == 0 && it.minor == 0 },
+                RsDevice.cuda(device)
+                    .takeUnless { it.address() == 0L }
+                    ?.let { mapCudaDeviceInfo(it) },
+                RsDevice.telemetry(device)
+                    .takeUnless { it.address() == 0L }
+                    ?.let { mapTelemetry(it) },
+                RsDevice.max_concurrent_sessions(device).takeIf { it >= 0 },
+                EncoderStatus.fromNative(RsDevice.encoder_status(device)),
+                RsDevice.optical_flow(device)
+                    .takeUnless { it.address() == 0L }
+                    ?.let { mapOpticalFlowCaps(it) }
             )
         }
         .toList()
 
+private fun mapApiVersion(apiVersion: MemorySegment) =
+    ApiVersion(RsApiVersion.major(apiVersion), RsApiVersion.minor(apiVersion))
+
+private fun mapCudaDeviceInfo(cudaDeviceInfo: MemorySegment) =
+    CudaDeviceInfo(
+        mapUuid(RsCudaDeviceInfo.uuid(cudaDeviceInfo)),
+        mapPciAddress(RsCudaDeviceInfo.pci_address(cudaDeviceInfo)),
+        mapApiVersion(RsCudaDeviceInfo.compute_capability(cudaDeviceInfo)),
+        RsCudaDeviceInfo.total_memory(cudaDeviceInfo),
+        RsCudaDeviceInfo.multiprocessor_count(cudaDeviceInfo),
+        RsCudaDeviceInfo.integrated(cudaDeviceInfo),
+        RsCudaDeviceInfo.tcc_driver(cudaDeviceInfo),
+        mapApiVersion(RsCudaDeviceInfo.driver_version(cudaDeviceInfo)),
+        RsCudaDeviceInfo.nvml_index(cudaDeviceInfo).takeIf { it >= 0 }
+    )
+
+private fun mapTelemetry(telemetry: MemorySegment) =
+    Telemetry(
+        RsTelemetry.gpu_utilization(telemetry).takeIf { it >= 0 },
+        RsTelemetry.encoder_utilization(telemetry).takeIf { it >= 0 },
+        RsTelemetry.decoder_utilization(telemetry).takeIf { it >= 0 },
+        RsTelemetry.encoder_sessions(telemetry).takeIf { it >= 0 },
+        RsTelemetry.encoder_average_fps(telemetry).takeIf { it >= 0 },
+        RsTelemetry.encoder_average_latency(telemetry).takeIf { it >= 0 },
+        RsTelemetry.temperature(telemetry).takeIf { it >= 0 },
+        RsTelemetry.power_usage(telemetry).takeIf { it >= 0 },
+        RsTelemetry.memory_total(telemetry).takeIf { it >= 0 },
+        RsTelemetry.memory_used(telemetry).takeIf { it >= 0 }
+    )
+
+private fun mapOpticalFlowCaps(opticalFlowCaps: MemorySegment) =
+    OpticalFlowCaps(
+        mapGridSizes(
+            RsOpticalFlowCaps.output_grid_sizes(opticalFlowCaps),
+            RsOpticalFlowCaps.num_output_grid_sizes(opticalFlowCaps)
+        ),
+        mapGridSizes(
+            RsOpticalFlowCaps.hint_grid_sizes(opticalFlowCaps),
+            RsOpticalFlowCaps.num_hint_grid_sizes(opticalFlowCaps)
+        ),
+        RsOpticalFlowCaps.hint_with_optical_flow(opticalFlowCaps),
+        RsOpticalFlowCaps.hint_with_stereo_disparity(opticalFlowCaps),
+        RsOpticalFlowCaps.min_width(opticalFlowCaps),
+        RsOpticalFlowCaps.min_height(opticalFlowCaps),
+        RsOpticalFlowCaps.max_width(opticalFlowCaps),
+        RsOpticalFlowCaps.max_height(opticalFlowCaps),
+        RsOpticalFlowCaps.roi_supported(opticalFlowCaps),
+        RsOpticalFlowCaps.max_roi_count(opticalFlowCaps)
+    )
+
+private fun mapGridSizes(gridSizes: MemorySegment, numGridSizes: Int) =
+    if (numGridSizes == 0)
+        emptyList()
+    else
+        gridSizes
+            .reinterpret(ValueLayout.JAVA_INT.byteSize() * numGridSizes)
+            .toArray(ValueLayout.JAVA_INT)
+            .toList()
+
+private fun mapUuid(uuid: MemorySegment): UUID? {
+    val bytes = uuid.toArray(ValueLayout.JAVA_BYTE)
+    if (bytes.all { it == NUL }) {
+        return null
+    }
+
+    val buffer = ByteBuffer.wrap(bytes)
+
+    return UUID(buffer.getLong(), buffer.getLong())
+}
+
+private fun mapPciAddress(pciAddress: MemorySegment) =
+    PciAddress(
+        RsPciAddress.domain(pciAddress),
+        RsPciAddress.bus(pciAddress).toUByte().toInt(),
+        RsPciAddress.device(pciAddress).toUByte().toInt(),
+        RsPciAddress.function(pciAddress).toUByte().toInt()
+    )
+
 private fun mapCodecDetails(codecs: MemorySegment, numCodecs: Int) =
     (0 until numCodecs)
         .asSequence()
@@ -88,9 +331,50 @@ private fun mapCodecDetails(codecs: MemorySegment, numCodecs: Int) =
                     RsCodecDetails.encoding_specs(codecDetails),
                     RsCodecDetails.num_encoding_specs(codecDetails)
                 ),
+                RsCodecDetails.nvenc_caps(codecDetails)
+                    .takeUnless { it.address() == 0L }
+                    ?.let { mapNvencCaps(it) },
+                RsCodecDetails.presets(codecDetails)
+                    .takeUnless { it.address() == 0L }
+                    ?.let { mapNvencPresets(it, RsCodecDetails.num_presets(codecDetails)) },
+                PixelFormat.fromNativeMask(RsCodecDetails.input_formats(codecDetails))
             )
         }.associateBy { it.codec }
 
+private fun mapNvencPresets(presets: MemorySegment, numPresets: Int) =
+    (0 until numPresets)
+        .asSequence()
+        .map { i -> RsNvencPreset.asSlice(presets, i.toLong()) }
+        .map { preset ->
+            NvencPreset(
+                EncodePreset.fromNative(RsNvencPreset.preset(preset)),
+                mapUuid(RsNvencPreset.guid(preset)) ?: UUID(0L, 0L),
+                mapPresetConfigs(RsNvencPreset.configs(preset), RsNvencPreset.num_configs(preset))
+            )
+        }
+        .toList()
+
+private fun mapPresetConfigs(presetConfigs: MemorySegment, numPresetConfigs: Int) =
+    (0 until numPresetConfigs)
+        .asSequence()
+        .map { i -> RsPresetConfig.asSlice(presetConfigs, i.toLong()) }
+        .map { presetConfig ->
+            PresetConfig(
+                TuningInfo.fromNative(RsPresetConfig.tuning_info(presetConfig)),
+                RsPresetConfig.gop_length(presetConfig).toUInt().toLong(),
+                RsPresetConfig.frame_interval_p(presetConfig),
+                RateControlMode.fromNative(RsPresetConfig.rate_control_mode(presetConfig)),
+                MultiPass.fromNative(RsPresetConfig.multi_pass(presetConfig)),
+                RsPresetConfig.lookahead(presetConfig),
+                RsPresetConfig.lookahead_depth(presetConfig).toUShort().toInt(),
+                RsPresetConfig.adaptive_quantization(presetConfig),
+                RsPresetConfig.temporal_adaptive_quantization(presetConfig),
+                RsPresetConfig.average_bitrate(presetConfig).toUInt().toLong(),
+                RsPresetConfig.max_bitrate(presetConfig).toUInt().toLong()
+            )
+        }
+        .toList()
+
 private fun mapDecodingSpecs(decodingSpecs: MemorySegment, numDecodingSpecs: Int) =
     (0 until numDecodingSpecs)
         .asSequence()
@@ -101,6 +385,13 @@ private fun mapDecodingSpecs(decodingSpecs: MemorySegment, numDecodingSpecs: Int
                 ColorDepth.fromNative(RsDecodingSpec.color_depth(decodingSpec)),
                 RsDecodingSpec.max_width(decodingSpec),
                 RsDecodingSpec.max_height(decodingSpec),
+                RsDecodingSpec.min_width(decodingSpec),
+                RsDecodingSpec.min_height(decodingSpec),
+                RsDecodingSpec.max_mb_count(decodingSpec),
+                OutputFormat.fromNativeMask(RsDecodingSpec.output_formats(decodingSpec)),
+                mapThreeValue(RsDecodingSpec.histogram_supported(decodingSpec)),
+                RsDecodingSpec.counter_bit_depth(decodingSpec).toUByte().toInt(),
+                RsDecodingSpec.max_histogram_bins(decodingSpec).toUShort().toInt(),
             )
         }
         .toList()
@@ -116,23 +407,139 @@ private fun mapEncodingSpecs(encodingSpecs: MemorySegment, numEncodingSpecs: Int
                 EncodeProfile.fromNative(RsEncodingSpec.profile(encodingSpec)),
                 RsEncodingSpec.max_width(encodingSpec),
                 RsEncodingSpec.max_height(encodingSpec),
-                when (RsEncodingSpec.b_frames_supported(encodingSpec)) {
-                    0 -> false
-                    1 -> true
-                    else -> null
-                }
+                mapThreeValue(RsEncodingSpec.b_frames_supported(encodingSpec)),
+                mapUuid(RsEncodingSpec.profile_guid(encodingSpec))
             )
         }
         .toList()
 
-private fun mapException(errno: Int) =
-    when (errno) {
-        EncDecHwscan.CriticalError() -> CriticalErrorException()
-        EncDecHwscan.DriverFailure() -> DriverFailureException()
-        EncDecHwscan.OperationFailed() -> OperationFailedException()
-        EncDecHwscan.ConversionFailed() -> ConversionFailedException()
+@Suppress("LongMethod")
+private fun mapNvencCaps(nvencCaps: MemorySegment) =
+    NvencCaps(
+        numMaxBframes = RsNvencCaps.num_max_bframes(nvencCaps).takeIf { it >= 0 },
+        supportedRatecontrolModes = RsNvencCaps.supported_ratecontrol_modes(nvencCaps).takeIf { it >= 0 },
+        supportFieldEncoding = RsNvencCaps.support_field_encoding(nvencCaps).takeIf { it >= 0 },
+        supportMonochrome = mapThreeValue(RsNvencCaps.support_monochrome(nvencCaps)),
+        supportFmo = mapThreeValue(RsNvencCaps.support_fmo(nvencCaps)),
+        supportQpelmv = mapThreeValue(RsNvencCaps.support_qpelmv(nvencCaps)),
+        supportBdirectMode = mapThreeValue(RsNvencCaps.support_bdirect_mode(nvencCaps)),
+        supportCabac = mapThreeValue(RsNvencCaps.support_cabac(nvencCaps)),
+        supportAdaptiveTransform = mapThreeValue(RsNvencCaps.support_adaptive_transform(nvencCaps)),
+        supportStereoMvc = mapThreeValue(RsNvencCaps.support_stereo_mvc(nvencCaps)),
+        numMaxTemporalLayers = RsNvencCaps.num_max_temporal_layers(nvencCaps).takeIf { it >= 0 },
+        supportHierarchicalPframes = mapThreeValue(RsNvencCaps.support_hierarchical_pframes(nvencCaps)),
+        supportHierarchicalBframes = mapThreeValue(RsNvencCaps.support_hierarchical_bframes(nvencCaps)),
+        levelMax = RsNvencCaps.level_max(nvencCaps).takeIf { it >= 0 },
+        levelMin = RsNvencCaps.level_min(nvencCaps).takeIf { it >= 0 },
+        separateColourPlane = mapThreeValue(RsNvencCaps.separate_colour_plane(nvencCaps)),
+        widthMax = RsNvencCaps.width_max(nvencCaps).takeIf { it >= 0 },
+        heightMax = RsNvencCaps.height_max(nvencCaps).takeIf { it >= 0 },
+        supportTemporalSvc = mapThreeValue(RsNvencCaps.support_temporal_svc(nvencCaps)),
+        supportDynResChange = mapThreeValue(RsNvencCaps.support_dyn_res_change(nvencCaps)),
+        supportDynBitrateChange = mapThreeValue(RsNvencCaps.support_dyn_bitrate_change(nvencCaps)),
+        supportDynForceConstqp = mapThreeValue(RsNvencCaps.support_dyn_force_constqp(nvencCaps)),
+        supportDynRcmodeChange = mapThreeValue(RsNvencCaps.support_dyn_rcmode_change(nvencCaps)),
+        supportSubframeReadback = mapThreeValue(RsNvencCaps.support_subframe_readback(nvencCaps)),
+        supportConstrainedEncoding = mapThreeValue(RsNvencCaps.support_constrained_encoding(nvencCaps)),
+        supportIntraRefresh = mapThreeValue(RsNvencCaps.support_intra_refresh(nvencCaps)),
+        supportCustomVbvBufSize = mapThreeValue(RsNvencCaps.support_custom_vbv_buf_size(nvencCaps)),
+        supportDynamicSliceMode = mapThreeValue(RsNvencCaps.support_dynamic_slice_mode(nvencCaps)),
+        supportRefPicInvalidation = mapThreeValue(RsNvencCaps.support_ref_pic_invalidation(nvencCaps)),
+        preprocSupport = RsNvencCaps.preproc_support(nvencCaps).takeIf { it >= 0 },
+        asyncEncodeSupport = mapThreeValue(RsNvencCaps.async_encode_support(nvencCaps)),
+        mbNumMax = RsNvencCaps.mb_num_max(nvencCaps).takeIf { it >= 0 },
+        mbPerSecMax = RsNvencCaps.mb_per_sec_max(nvencCaps).takeIf { it >= 0 },
+        supportYuv444Encode = mapThreeValue(RsNvencCaps.support_yuv444_encode(nvencCaps)),
+        supportLosslessEncode = mapThreeValue(RsNvencCaps.support_lossless_encode(nvencCaps)),
+        supportSao = mapThreeValue(RsNvencCaps.support_sao(nvencCaps)),
+        supportMeonlyMode = mapThreeValue(RsNvencCaps.support_meonly_mode(nvencCaps)),
+        supportLookahead = mapThreeValue(RsNvencCaps.support_lookahead(nvencCaps)),
+        supportTemporalAq = mapThreeValue(RsNvencCaps.support_temporal_aq(nvencCaps)),
+        support10bitEncode = mapThreeValue(RsNvencCaps.support_10bit_encode(nvencCaps)),
+        numMaxLtrFrames = RsNvencCaps.num_max_ltr_frames(nvencCaps).takeIf { it >= 0 },
+        supportWeightedPrediction = mapThreeValue(RsNvencCaps.support_weighted_prediction(nvencCaps)),
+        dynamicQueryEncoderCapacity = RsNvencCaps.dynamic_query_encoder_capacity(nvencCaps).takeIf { it >= 0 },
+        supportBframeRefMode = RsNvencCaps.support_bframe_ref_mode(nvencCaps).takeIf { it >= 0 },
+        supportEmphasisLevelMap = mapThreeValue(RsNvencCaps.support_emphasis_level_map(nvencCaps)),
+        widthMin = RsNvencCaps.width_min(nvencCaps).takeIf { it >= 0 },
+        heightMin = RsNvencCaps.height_min(nvencCaps).takeIf { it >= 0 },
+        supportMultipleRefFrames = mapThreeValue(RsNvencCaps.support_multiple_ref_frames(nvencCaps)),
+        supportAlphaLayerEncoding = mapThreeValue(RsNvencCaps.support_alpha_layer_encoding(nvencCaps)),
+        numEncoderEngines = RsNvencCaps.num_encoder_engines(nvencCaps).takeIf { it >= 0 },
+        singleSliceIntraRefresh = mapThreeValue(RsNvencCaps.single_slice_intra_refresh(nvencCaps)),
+        disableEncStateAdvance = mapThreeValue(RsNvencCaps.disable_enc_state_advance(nvencCaps)),
+        outputReconSurface = mapThreeValue(RsNvencCaps.output_recon_surface(nvencCaps)),
+        outputBlockStats = mapThreeValue(RsNvencCaps.output_block_stats(nvencCaps)),
+        outputRowStats = mapThreeValue(RsNvencCaps.output_row_stats(nvencCaps)),
+        supportTemporalFilter = mapThreeValue(RsNvencCaps.support_temporal_filter(nvencCaps)),
+        supportLookaheadLevel = mapThreeValue(RsNvencCaps.support_lookahead_level(nvencCaps)),
+        supportUnidirectionalB = mapThreeValue(RsNvencCaps.support_unidirectional_b(nvencCaps)),
+        raw = mapRawNvencCaps(RsNvencCaps.raw_caps(nvencCaps), RsNvencCaps.num_raw_caps(nvencCaps))
+    )
+
+private fun mapRawNvencCaps(rawCaps: MemorySegment, numRawCaps: Int) =
+    (0 until numRawCaps)
+        .asSequence()
+        .map { i -> RsNvencCapValue.asSlice(rawCaps, i.toLong()) }
+        .associate { capValue -> RsNvencCapValue.cap(capValue) to RsNvencCapValue.value(capValue) }
+
+private fun mapThreeValue(value: Int) =
+    when (value) {
+        0 -> false
+        1 -> true
+        else -> null
+    }
+
+private fun mapDiagnostics(diagnostics: MemorySegment, numDiagnostics: Int) =
+    (0 until numDiagnostics)
+        .asSequence()
+        .map { i -> RsDiagnostic.asSlice(diagnostics, i.toLong()) }
+        .map { diagnostic ->
+            Diagnostic(
+                Driver.fromNative(RsDiagnostic.driver(diagnostic)),
+                RsDiagnostic.ordinal(diagnostic).takeIf { it >= 0 }?.toByte(),
+                RsDiagnostic.path(diagnostic).readStringOrNull()?.let { Path(it) },
+                ErrorKind.fromNative(RsDiagnostic.kind(diagnostic)),
+                RsDiagnostic.native_code(diagnostic).takeIf { it != 0L },
+                RsDiagnostic.message(diagnostic).readStringOrNull() ?: ""
+            )
+        }
+        .toList()
+
+private fun mapLibraries(libraries: MemorySegment, numLibraries: Int) =
+    (0 until numLibraries)
+        .asSequence()
+        .map { i -> RsLibraryInfo.asSlice(libraries, i.toLong()) }
+        .map { library ->
+            LibraryInfo(
+                Driver.fromNative(RsLibraryInfo.driver(library)),
+                RsLibraryInfo.name(library).readStringOrNull() ?: "",
+                RsLibraryInfo.path(library).readStringOrNull()?.let { Path(it) },
+                RsLibraryInfo.error(library).readStringOrNull()
+            )
+        }
+        .toList()
+
+private fun mapException(errno: Int): EncDecHwscanException {
+    val message = Native.lastErrorMessage().readStringOrNull()
+    val backend = Native.lastErrorBackend().readStringOrNull()
+    val nativeCode = Native.lastErrorNativeCode().takeIf { it != 0L }
+
+    return when (errno) {
+        EncDecHwscan.CriticalError() -> CriticalErrorException(message, backend, nativeCode)
+        EncDecHwscan.DriverFailure(),
+        EncDecHwscan.NoDevice(),
+        EncDecHwscan.InsufficientDriver(),
+        EncDecHwscan.DriverMismatch(),
+        EncDecHwscan.DeviceUnavailable(),
+        EncDecHwscan.InitFailed() -> DriverFailureException(message, backend, nativeCode)
+        EncDecHwscan.OperationFailed(),
+        EncDecHwscan.NvmlFailed(),
+        EncDecHwscan.OpticalFlowFailed() -> OperationFailedException(message, backend, nativeCode)
+        EncDecHwscan.ConversionFailed() -> ConversionFailedException(message, backend, nativeCode)
         else -> UnrecognizedErrorException(errno)
     }
+}
 
 private fun MemorySegment.readStringOrNull(): String? {
     if (address() == 0L) {
@@ -148,6 +555,8 @@ private fun MemorySegment.readStringOrNull(): String? {
 
 private const val NUL = 0x0.toByte()
 
+private const val UUID_SIZE = 16
+
 private fun MemorySegment.strlen(): Int {
     for (i in 0..Int.MAX_VALUE) {
         val byte = getAtIndex(ValueLayout.JAVA_BYTE, i.toLong())
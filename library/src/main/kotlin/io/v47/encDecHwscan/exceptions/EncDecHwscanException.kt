@@ -16,27 +16,41 @@
  */
 package io.v47.encDecHwscan.exceptions
 
-sealed class EncDecHwscanException(message: String? = null) : Exception(message)
+/**
+ * Base class of all errors reported by the native library.
+ *
+ * [backend] names the backend the error occurred in (`nvidia` or `vaapi`), and [nativeCode] contains
+ * the status code returned by the driver API, if available.
+ */
+sealed class EncDecHwscanException(
+    message: String? = null,
+    val backend: String? = null,
+    val nativeCode: Long? = null
+) : Exception(message)
 
 /**
  * Indicates an unexpected error occurred in the native library.
  */
-class CriticalErrorException : EncDecHwscanException()
+class CriticalErrorException(message: String? = null, backend: String? = null, nativeCode: Long? = null) :
+    EncDecHwscanException(message, backend, nativeCode)
 
 /**
  * Indicates that the native library was unable to query a device driver for information.
  */
-class DriverFailureException : EncDecHwscanException()
+class DriverFailureException(message: String? = null, backend: String? = null, nativeCode: Long? = null) :
+    EncDecHwscanException(message, backend, nativeCode)
 
 /**
  * Indicates a generic operation failure in the native library.
  */
-class OperationFailedException : EncDecHwscanException()
+class OperationFailedException(message: String? = null, backend: String? = null, nativeCode: Long? = null) :
+    EncDecHwscanException(message, backend, nativeCode)
 
 /**
  * Indicates a failure to convert the native representation to Kotlin/JVM types.
  */
-class ConversionFailedException : EncDecHwscanException()
+class ConversionFailedException(message: String? = null, backend: String? = null, nativeCode: Long? = null) :
+    EncDecHwscanException(message, backend, nativeCode)
 
 /**
  * Indicates that some other unrecognized error occurred in the native library.
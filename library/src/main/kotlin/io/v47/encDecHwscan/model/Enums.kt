@@ -35,7 +35,7 @@ enum class Driver(private val nativeValue: Int) {
 /**
  * Specifies all recognized video codecs.
  */
-enum class Codec(private val nativeValue: Int) {
+enum class Codec(internal val nativeValue: Int) {
     Mpeg1(EncDecHwscan.Mpeg1()),
     Mpeg2(EncDecHwscan.Mpeg2()),
     Mpeg4(EncDecHwscan.Mpeg4()),
@@ -65,6 +65,43 @@ enum class Chroma(private val nativeValue: Int) {
     }
 }
 
+/**
+ * Specifies the surface formats a decoder can write its output to.
+ */
+enum class OutputFormat(private val nativeValue: Int) {
+    Nv12(EncDecHwscan.Nv12()),
+    P016(EncDecHwscan.P016()),
+    Yuv444Planar(EncDecHwscan.Yuv444Planar()),
+    Yuv444Planar16(EncDecHwscan.Yuv444Planar16());
+
+    companion object {
+        internal fun fromNativeMask(mask: Int): Set<OutputFormat> =
+            entries.filter { mask and (1 shl it.nativeValue) != 0 }.toSet()
+    }
+}
+
+/**
+ * Specifies the buffer formats NVENC accepts as input.
+ */
+enum class PixelFormat(private val nativeValue: Int) {
+    Nv12(EncDecHwscan.PixelFormat_Nv12()),
+    Yv12(EncDecHwscan.PixelFormat_Yv12()),
+    Iyuv(EncDecHwscan.PixelFormat_Iyuv()),
+    Yuv444(EncDecHwscan.PixelFormat_Yuv444()),
+    P010(EncDecHwscan.PixelFormat_P010()),
+    Yuv444Bit10(EncDecHwscan.PixelFormat_Yuv444Bit10()),
+    Argb(EncDecHwscan.PixelFormat_Argb()),
+    Argb10(EncDecHwscan.PixelFormat_Argb10()),
+    Ayuv(EncDecHwscan.PixelFormat_Ayuv()),
+    Abgr(EncDecHwscan.PixelFormat_Abgr()),
+    Abgr10(EncDecHwscan.PixelFormat_Abgr10());
+
+    companion object {
+        internal fun fromNativeMask(mask: Int): Set<PixelFormat> =
+            entries.filter { mask and (1 shl it.nativeValue) != 0 }.toSet()
+    }
+}
+
 /**
  * Specifies the recognized color depths.
  */
@@ -79,18 +116,131 @@ enum class ColorDepth(private val nativeValue: Int) {
 }
 
 /**
- * Specifies the recognized encoding profiles.
+ * Specifies the recognized encoding profiles. [Other] is a profile reported by NVENC that this
+ * library doesn't know about, it can only be told apart by [EncodingSpec.profileGuid].
  */
 enum class EncodeProfile(private val nativeValue: Int) {
+    Other(EncDecHwscan.Other()),
+    AutoSelect(EncDecHwscan.AutoSelect()),
     Baseline(EncDecHwscan.Baseline()),
+    Frext(EncDecHwscan.Frext()),
     Main(EncDecHwscan.Main()),
     Main10(EncDecHwscan.Main10()),
     High(EncDecHwscan.High()),
+    ConstrainedHigh(EncDecHwscan.ConstrainedHigh()),
+    ProgressiveHigh(EncDecHwscan.ProgressiveHigh()),
     High10(EncDecHwscan.High10()),
     High12(EncDecHwscan.High12()),
+    Stereo(EncDecHwscan.Stereo()),
     High444(EncDecHwscan.High444());
 
     companion object {
         internal fun fromNative(value: Int): EncodeProfile = entries.first { it.nativeValue == value }
     }
 }
+
+/**
+ * Specifies the NVENC presets, [P1] is the fastest and [P7] the one with the best quality. [Legacy]
+ * is any other preset reported by the driver and can only be told apart by its GUID.
+ */
+enum class EncodePreset(private val nativeValue: Int) {
+    Legacy(EncDecHwscan.Legacy()),
+    P1(EncDecHwscan.P1()),
+    P2(EncDecHwscan.P2()),
+    P3(EncDecHwscan.P3()),
+    P4(EncDecHwscan.P4()),
+    P5(EncDecHwscan.P5()),
+    P6(EncDecHwscan.P6()),
+    P7(EncDecHwscan.P7());
+
+    companion object {
+        internal fun fromNative(value: Int): EncodePreset = entries.first { it.nativeValue == value }
+    }
+}
+
+/**
+ * Specifies the use case an NVENC preset configuration is tuned for.
+ */
+enum class TuningInfo(private val nativeValue: Int) {
+    HighQuality(EncDecHwscan.HighQuality()),
+    LowLatency(EncDecHwscan.LowLatency()),
+    UltraLowLatency(EncDecHwscan.UltraLowLatency()),
+    Lossless(EncDecHwscan.Lossless()),
+    UltraHighQuality(EncDecHwscan.UltraHighQuality());
+
+    companion object {
+        internal fun fromNative(value: Int): TuningInfo = entries.first { it.nativeValue == value }
+    }
+}
+
+/**
+ * Specifies the NVENC rate control modes.
+ */
+enum class RateControlMode(private val nativeValue: Int) {
+    ConstQp(EncDecHwscan.ConstQp()),
+    Vbr(EncDecHwscan.Vbr()),
+    Cbr(EncDecHwscan.Cbr());
+
+    companion object {
+        internal fun fromNative(value: Int): RateControlMode = entries.first { it.nativeValue == value }
+    }
+}
+
+/**
+ * Specifies the NVENC multi-pass modes, the first pass of two-pass encoding runs at either quarter
+ * or full resolution.
+ */
+enum class MultiPass(private val nativeValue: Int) {
+    SinglePass(EncDecHwscan.SinglePass()),
+    TwoPassQuarterResolution(EncDecHwscan.TwoPassQuarterResolution()),
+    TwoPassFullResolution(EncDecHwscan.TwoPassFullResolution());
+
+    companion object {
+        internal fun fromNative(value: Int): MultiPass = entries.first { it.nativeValue == value }
+    }
+}
+
+/**
+ * Specifies whether the encoding capabilities of a [Device] could be probed during the scan.
+ *
+ * A GPU is busy if all of its encode sessions are in use, or if it runs in exclusive-process compute
+ * mode and another process holds its context. [Cached] capabilities are taken from the last
+ * successful scan of the device, [Busy] devices haven't been scanned successfully before and lack
+ * the capabilities that couldn't be probed. [UnsupportedApi] devices have a driver older than NVENC
+ * 7.0, their encoding capabilities aren't probed.
+ */
+enum class EncoderStatus(private val nativeValue: Int) {
+    Available(EncDecHwscan.Available()),
+    Cached(EncDecHwscan.Cached()),
+    Busy(EncDecHwscan.Busy()),
+    UnsupportedApi(EncDecHwscan.UnsupportedApi());
+
+    companion object {
+        internal fun fromNative(value: Int): EncoderStatus = entries.first { it.nativeValue == value }
+    }
+}
+
+/**
+ * Specifies the kinds of errors reported in a [Diagnostic].
+ *
+ * [NoDevice], [InsufficientDriver], [DriverMismatch], [DeviceUnavailable] and [InitFailed] report why
+ * the driver couldn't be initialized. A later scan tries to initialize it again. The native code of
+ * [NvmlFailed] is an `nvmlReturn_t`, the one of [OpticalFlowFailed] an `NV_OF_STATUS`.
+ */
+enum class ErrorKind(private val nativeValue: Int) {
+    CriticalError(EncDecHwscan.CriticalError()),
+    DriverFailure(EncDecHwscan.DriverFailure()),
+    OperationFailed(EncDecHwscan.OperationFailed()),
+    ConversionFailed(EncDecHwscan.ConversionFailed()),
+    NoDevice(EncDecHwscan.NoDevice()),
+    InsufficientDriver(EncDecHwscan.InsufficientDriver()),
+    DriverMismatch(EncDecHwscan.DriverMismatch()),
+    DeviceUnavailable(EncDecHwscan.DeviceUnavailable()),
+    InitFailed(EncDecHwscan.InitFailed()),
+    NvmlFailed(EncDecHwscan.NvmlFailed()),
+    OpticalFlowFailed(EncDecHwscan.OpticalFlowFailed());
+
+    companion object {
+        internal fun fromNative(value: Int): ErrorKind = entries.first { it.nativeValue == value }
+    }
+}
@@ -17,22 +17,35 @@
 package io.v47.encDecHwscan.model
 
 import com.fasterxml.jackson.annotation.JsonAlias
+import com.fasterxml.jackson.annotation.JsonValue
 import io.quarkus.runtime.annotations.RegisterForReflection
 import java.nio.file.Path
+import java.util.UUID
 
 /**
  * Contains detailed information about decoding capabilities.
+ *
+ * Numeric limits are `0` and [outputFormats] is empty if the driver doesn't report them.
  */
 @RegisterForReflection
 data class DecodingSpec(
     val chroma: Chroma,
     val colorDepth: ColorDepth,
     val maxWidth: Int,
-    val maxHeight: Int
+    val maxHeight: Int,
+    val minWidth: Int = 0,
+    val minHeight: Int = 0,
+    val maxMbCount: Int = 0,
+    val outputFormats: Set<OutputFormat> = emptySet(),
+    val histogramSupported: Boolean? = null,
+    val counterBitDepth: Int = 0,
+    val maxHistogramBins: Int = 0
 )
 
 /**
  * Contains detailed information about encoding capabilities.
+ *
+ * [profileGuid] is the profile GUID reported by NVENC, `null` for other drivers.
  */
 @RegisterForReflection
 data class EncodingSpec(
@@ -42,21 +55,232 @@ data class EncodingSpec(
     val maxWidth: Int,
     val maxHeight: Int,
     @JsonAlias("bframesSupported")
-    val bFramesSupported: Boolean?
+    val bFramesSupported: Boolean?,
+    val profileGuid: UUID? = null
+)
+
+/**
+ * Contains all capabilities NVENC reports for a codec, see `NV_ENC_CAPS` in `nvEncodeAPI.h` for
+ * their meaning.
+ *
+ * A capability is `null` if the driver doesn't support querying it. [raw] contains every value
+ * the driver reported, keyed by its `NV_ENC_CAPS` value, including capabilities this library
+ * doesn't know about yet.
+ */
+@RegisterForReflection
+data class NvencCaps(
+    val numMaxBframes: Int?,
+    val supportedRatecontrolModes: Int?,
+    val supportFieldEncoding: Int?,
+    val supportMonochrome: Boolean?,
+    val supportFmo: Boolean?,
+    val supportQpelmv: Boolean?,
+    val supportBdirectMode: Boolean?,
+    val supportCabac: Boolean?,
+    val supportAdaptiveTransform: Boolean?,
+    val supportStereoMvc: Boolean?,
+    val numMaxTemporalLayers: Int?,
+    val supportHierarchicalPframes: Boolean?,
+    val supportHierarchicalBframes: Boolean?,
+    val levelMax: Int?,
+    val levelMin: Int?,
+    val separateColourPlane: Boolean?,
+    val widthMax: Int?,
+    val heightMax: Int?,
+    val supportTemporalSvc: Boolean?,
+    val supportDynResChange: Boolean?,
+    val supportDynBitrateChange: Boolean?,
+    val supportDynForceConstqp: Boolean?,
+    val supportDynRcmodeChange: Boolean?,
+    val supportSubframeReadback: Boolean?,
+    val supportConstrainedEncoding: Boolean?,
+    val supportIntraRefresh: Boolean?,
+    val supportCustomVbvBufSize: Boolean?,
+    val supportDynamicSliceMode: Boolean?,
+    val supportRefPicInvalidation: Boolean?,
+    val preprocSupport: Int?,
+    val asyncEncodeSupport: Boolean?,
+    val mbNumMax: Int?,
+    val mbPerSecMax: Int?,
+    val supportYuv444Encode: Boolean?,
+    val supportLosslessEncode: Boolean?,
+    val supportSao: Boolean?,
+    val supportMeonlyMode: Boolean?,
+    val supportLookahead: Boolean?,
+    val supportTemporalAq: Boolean?,
+    val support10bitEncode: Boolean?,
+    val numMaxLtrFrames: Int?,
+    val supportWeightedPrediction: Boolean?,
+    val dynamicQueryEncoderCapacity: Int?,
+    val supportBframeRefMode: Int?,
+    val supportEmphasisLevelMap: Boolean?,
+    val widthMin: Int?,
+    val heightMin: Int?,
+    val supportMultipleRefFrames: Boolean?,
+    val supportAlphaLayerEncoding: Boolean?,
+    val numEncoderEngines: Int?,
+    val singleSliceIntraRefresh: Boolean?,
+    val disableEncStateAdvance: Boolean?,
+    val outputReconSurface: Boolean?,
+    val outputBlockStats: Boolean?,
+    val outputRowStats: Boolean?,
+    val supportTemporalFilter: Boolean?,
+    val supportLookaheadLevel: Boolean?,
+    val supportUnidirectionalB: Boolean?,
+    val raw: Map<Int, Int>
+)
+
+/**
+ * Default encoder configuration of a preset for one [tuningInfo].
+ *
+ * [gopLength] is `4294967295` for an infinite GOP. [frameIntervalP] is the distance between two
+ * P-frames, i.e. `1` means no B-frames. Bitrates are specified in bits per second, `0` lets the
+ * driver choose.
+ */
+@RegisterForReflection
+data class PresetConfig(
+    val tuningInfo: TuningInfo,
+    val gopLength: Long,
+    val frameIntervalP: Int,
+    val rateControlMode: RateControlMode,
+    val multiPass: MultiPass,
+    val lookahead: Boolean,
+    val lookaheadDepth: Int,
+    val adaptiveQuantization: Boolean,
+    val temporalAdaptiveQuantization: Boolean,
+    val averageBitrate: Long,
+    val maxBitrate: Long
+)
+
+/**
+ * A preset NVENC supports for a codec, [guid] is the preset GUID reported by the driver.
+ *
+ * [configs] contains the default configuration for every tuning info the driver accepts, it's empty if
+ * [Device.nvencApiVersion] is older than 12.2.
+ */
+@RegisterForReflection
+data class NvencPreset(
+    val preset: EncodePreset,
+    val guid: UUID,
+    val configs: List<PresetConfig>
 )
 
 /**
  * Contains the detailed capabilities for the specified [codec].
+ *
+ * [nvencCaps] and [presets] are `null` and [inputFormats] is empty if the codec can't be encoded
+ * using NVENC.
  */
 @RegisterForReflection
 data class CodecDetails(
     val codec: Codec,
     val decodingSpecs: List<DecodingSpec>,
     val encodingSpecs: List<EncodingSpec>,
+    val nvencCaps: NvencCaps? = null,
+    val presets: List<NvencPreset>? = null,
+    val inputFormats: Set<PixelFormat> = emptySet(),
+)
+
+/**
+ * Version of a driver API, e.g. `12.2` for NVENC.
+ */
+@RegisterForReflection
+data class ApiVersion(
+    val major: Int,
+    val minor: Int
+) {
+    override fun toString() = "$major.$minor"
+}
+
+/**
+ * PCI location of a device, formatted as `domain:bus:device.function` in hexadecimal, e.g.
+ * `0000:65:00.0`.
+ */
+@RegisterForReflection
+data class PciAddress(
+    val domain: Int,
+    val bus: Int,
+    val device: Int,
+    val function: Int
+) {
+    @JsonValue
+    override fun toString() = "%04x:%02x:%02x.%x".format(domain, bus, device, function)
+}
+
+/**
+ * Contains the properties of a CUDA device.
+ *
+ * [uuid] is `null` if the driver doesn't report one, `nvidia-smi` prints it with a `GPU-` prefix.
+ * [totalMemory] is specified in bytes.
+ *
+ * [nvmlIndex] is the index `nvidia-smi` shows for the device, `null` if NVML isn't available. Unlike
+ * [Device.ordinal] it isn't remapped by `CUDA_VISIBLE_DEVICES`.
+ */
+@RegisterForReflection
+data class CudaDeviceInfo(
+    val uuid: UUID?,
+    val pciAddress: PciAddress,
+    val computeCapability: ApiVersion,
+    val totalMemory: Long,
+    val multiprocessorCount: Int,
+    val integrated: Boolean,
+    val tccDriver: Boolean,
+    val driverVersion: ApiVersion,
+    val nvmlIndex: Int? = null
+)
+
+/**
+ * Contains the capabilities of the optical flow accelerator of an NVIDIA device.
+ *
+ * [outputGridSizes] and [hintGridSizes] are the supported grid sizes in pixels. [hintWithOpticalFlow]
+ * and [hintWithStereoDisparity] tell whether external hints are supported in the respective mode.
+ * [maxRoiCount] is `0` if regions of interest aren't supported.
+ */
+@RegisterForReflection
+data class OpticalFlowCaps(
+    val outputGridSizes: List<Int>,
+    val hintGridSizes: List<Int>,
+    val hintWithOpticalFlow: Boolean,
+    val hintWithStereoDisparity: Boolean,
+    val minWidth: Int,
+    val minHeight: Int,
+    val maxWidth: Int,
+    val maxHeight: Int,
+    val roiSupported: Boolean,
+    val maxRoiCount: Int
+)
+
+/**
+ * Contains the runtime telemetry of a device as reported by NVML, every value is `null` if the
+ * driver doesn't report it.
+ *
+ * Utilizations are specified in percent, [encoderAverageLatency] in microseconds, [temperature] in
+ * degrees Celsius, [powerUsage] in milliwatts and memory in bytes.
+ */
+@RegisterForReflection
+data class Telemetry(
+    val gpuUtilization: Int?,
+    val encoderUtilization: Int?,
+    val decoderUtilization: Int?,
+    val encoderSessions: Int?,
+    val encoderAverageFps: Int?,
+    val encoderAverageLatency: Int?,
+    val temperature: Int?,
+    val powerUsage: Int?,
+    val memoryTotal: Long?,
+    val memoryUsed: Long?
 )
 
 /**
  * Contains the details codec support and capabilities for a encoding/decoding device.
+ *
+ * [nvencApiVersion] is the NVENC API version negotiated with the driver, it's `null` for
+ * devices that don't use NVENC. [cuda] is `null` for devices that don't use CUDA. [telemetry] is only
+ * available for CUDA devices if NVML is installed, use [io.v47.encDecHwscan.refreshTelemetry] to
+ * update it. [maxConcurrentSessions] is only probed if [ScanOptions.probeEncoderSessions] is
+ * enabled. [encoderStatus] tells whether the encoding capabilities are up to date, or why they
+ * aren't. [opticalFlow] is only available for NVIDIA devices with an optical flow accelerator if
+ * `libnvidia-opticalflow.so.1` is installed.
  */
 @RegisterForReflection
 data class Device(
@@ -64,5 +288,89 @@ data class Device(
     val ordinal: Byte?,
     val path: Path?,
     val name: String?,
-    val codecs: Map<Codec, CodecDetails>
+    val codecs: Map<Codec, CodecDetails>,
+    val nvencApiVersion: ApiVersion? = null,
+    val cuda: CudaDeviceInfo? = null,
+    val telemetry: Telemetry? = null,
+    val maxConcurrentSessions: Int? = null,
+    val encoderStatus: EncoderStatus = EncoderStatus.Available,
+    val opticalFlow: OpticalFlowCaps? = null
+)
+
+/**
+ * Describes a failure that occurred while scanning a backend or one of its devices.
+ *
+ * [ordinal] and [path] identify the affected device, both are `null` if the whole backend failed.
+ * [nativeCode] contains the status code returned by the driver API, if there is one.
+ */
+@RegisterForReflection
+data class Diagnostic(
+    val driver: Driver,
+    val ordinal: Byte?,
+    val path: Path?,
+    val kind: ErrorKind,
+    val nativeCode: Long?,
+    val message: String
+)
+
+/**
+ * Describes a driver library a backend tried to load, [name] is the name it's usually installed
+ * under.
+ *
+ * [path] is the file the library was loaded from, [error] describes why it couldn't be loaded.
+ */
+@RegisterForReflection
+data class LibraryInfo(
+    val driver: Driver,
+    val name: String,
+    val path: Path?,
+    val error: String?
+)
+
+/**
+ * Contains all devices that were scanned successfully and diagnostics for those that weren't.
+ *
+ * [libraries] lists the driver libraries every enabled backend tried to load, so a missing driver
+ * can be told apart from a driver without devices.
+ */
+@RegisterForReflection
+data class ScanResult(
+    val devices: List<Device>,
+    val diagnostics: List<Diagnostic>,
+    val libraries: List<LibraryInfo> = emptyList()
+)
+
+/**
+ * Restricts which drivers, devices and codecs are probed by [io.v47.encDecHwscan.scan].
+ *
+ * `null` or empty collections don't restrict anything.
+ *
+ * [probeEncoderSessions] determines [Device.maxConcurrentSessions] by opening NVENC sessions until
+ * the driver refuses another one. It's disabled by default because it briefly occupies every free
+ * session of the GPU.
+ *
+ * [nvidiaLibraryDirs] are searched for the NVIDIA libraries before the directories known to the
+ * dynamic linker. The libraries are loaded once per process, so they only take effect if passed to
+ * the first scan.
+ *
+ * [usePrimaryContext] probes NVIDIA devices with their primary CUDA context instead of a context
+ * created for the scan. It's cheaper and shares the context with CUDA work of the host application.
+ * Either way the current context of the calling thread is preserved.
+ *
+ * [nvidiaDeviceMask] uses the syntax of `CUDA_VISIBLE_DEVICES`: every entry is either an index as
+ * printed by `nvidia-smi`, a (prefix of a) UUID with an optional `GPU-` prefix, or a PCI bus ID.
+ * Indices are matched against [CudaDeviceInfo.nvmlIndex], not the CUDA ordinal, so unlike [cudaOrdinals]
+ * the mask selects the same devices regardless of how `CUDA_VISIBLE_DEVICES` remaps them. Without
+ * NVML they match nothing.
+ */
+data class ScanOptions(
+    val nvidia: Boolean = true,
+    val vaapi: Boolean = true,
+    val cudaOrdinals: Set<Byte>? = null,
+    val drmPaths: Set<Path>? = null,
+    val codecs: Set<Codec>? = null,
+    val probeEncoderSessions: Boolean = false,
+    val nvidiaLibraryDirs: List<Path> = emptyList(),
+    val nvidiaDeviceMask: List<String>? = null,
+    val usePrimaryContext: Boolean = false
 )
@@ -29,7 +29,10 @@ fn main() {
         .with_language(Language::C)
         .with_cpp_compat(false)
         .with_no_includes()
+        .with_sys_include("stdbool.h")
         .with_sys_include("stdint.h")
+        .include_item("OutputFormat")
+        .include_item("PixelFormat")
         .generate()
         .expect("Unable to generate C headers")
         .write_to_file("target/enc-dec-hwscan.h");
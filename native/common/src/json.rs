@@ -0,0 +1,73 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Versioned JSON representation of a [ScanResult].
+//!
+//! The document is a [ScanResult] with an additional `schemaVersion` field. Field names match
+//! the JSON produced by the Kotlin library, see the README for the full schema.
+//! [SCHEMA_VERSION] is incremented whenever a field is removed, renamed or changes its meaning,
+//! new fields may be added without a version change.
+
+use serde::{Deserialize, Serialize};
+
+use crate::owned::ScanResult;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VersionedRef<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    result: &'a ScanResult,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Versioned {
+    schema_version: u32,
+    #[serde(flatten)]
+    result: ScanResult,
+}
+
+pub fn to_json(result: &ScanResult) -> serde_json::Result<String> {
+    serde_json::to_string(&versioned(result))
+}
+
+pub fn to_json_pretty(result: &ScanResult) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&versioned(result))
+}
+
+/// Parses a document created by [to_json], fails if it was created with a newer
+/// [SCHEMA_VERSION].
+pub fn from_json(json: &str) -> serde_json::Result<ScanResult> {
+    let versioned: Versioned = serde_json::from_str(json)?;
+    if versioned.schema_version > SCHEMA_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported schema version {}, expected at most {}",
+            versioned.schema_version, SCHEMA_VERSION
+        )));
+    }
+
+    Ok(versioned.result)
+}
+
+fn versioned(result: &ScanResult) -> VersionedRef<'_> {
+    VersionedRef {
+        schema_version: SCHEMA_VERSION,
+        result,
+    }
+}
@@ -16,5 +16,8 @@
  */
 pub use crate::model::*;
 
+pub mod json;
+pub mod log;
 mod model;
+pub mod owned;
 mod utils;
@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Minimal logging facade used by all native crates.
+//!
+//! Messages are written to stderr by default. A custom sink can be installed with
+//! [set_log_sink], e.g. to forward messages to the `log` crate:
+//!
+//! ```ignore
+//! use enc_dec_hwscan::log::{set_log_sink, LogLevel};
+//!
+//! set_log_sink(|level, target: &str, message: &str| {
+//!     let level = match level {
+//!         LogLevel::Error => log::Level::Error,
+//!         LogLevel::Warn => log::Level::Warn,
+//!         LogLevel::Info => log::Level::Info,
+//!         LogLevel::Debug => log::Level::Debug,
+//!         LogLevel::Trace => log::Level::Trace,
+//!     };
+//!
+//!     log::log!(target: target, level, "{}", message);
+//! });
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Specifies the severity of a log message.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        };
+
+        f.pad(name)
+    }
+}
+
+/// Receives all log messages that pass the configured [LogLevel].
+///
+/// `target` is the module path the message originates from, e.g. `nvidia::caps::encode`.
+///
+/// Sinks are called concurrently from the worker threads a scan probes its devices on, and must
+/// not block waiting for the thread that started the scan.
+pub trait LogSink: Send + Sync {
+    fn log(&self, level: LogLevel, target: &str, message: &str);
+}
+
+impl<F> LogSink for F
+where
+    F: Fn(LogLevel, &str, &str) + Send + Sync,
+{
+    fn log(&self, level: LogLevel, target: &str, message: &str) {
+        self(level, target, message)
+    }
+}
+
+static SINK: RwLock<Option<Arc<dyn LogSink>>> = RwLock::new(None);
+static MAX_LEVEL: AtomicI32 = AtomicI32::new(LogLevel::Info as i32);
+
+/// Replaces the current sink, all following messages are passed to `sink` instead.
+pub fn set_log_sink(sink: impl LogSink + 'static) {
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(sink));
+}
+
+/// Restores the default sink which writes to stderr.
+pub fn reset_log_sink() {
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Sets the most verbose level that is still passed to the sink, defaults to [LogLevel::Info].
+pub fn set_max_log_level(level: LogLevel) {
+    MAX_LEVEL.store(level as i32, Ordering::Relaxed);
+}
+
+/// Returns whether messages of `level` are currently passed to the sink.
+pub fn log_enabled(level: LogLevel) -> bool {
+    level as i32 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Passes a message to the current sink, use the `log_*!` macros instead of calling this directly.
+pub fn log(level: LogLevel, target: &str, args: fmt::Arguments) {
+    if !log_enabled(level) {
+        return;
+    }
+
+    let message = fmt::format(args);
+    // the lock isn't held while the sink runs, so it may replace itself and can't stall writers
+    let sink = SINK.read().unwrap_or_else(|err| err.into_inner()).clone();
+
+    match sink {
+        Some(sink) => sink.log(level, target, &message),
+        None => eprintln!("enc-dec-hwscan {:<5} {}: {}", level, target, message),
+    }
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log::log($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::LogLevel::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::LogLevel::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::LogLevel::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::LogLevel::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::LogLevel::Trace, $($arg)+) };
+}
@@ -14,21 +14,57 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use crate::owned;
 use crate::utils::{drop_vec, vec_to_ptr};
 
-use std::ffi::{c_char, CStr, CString};
-use std::fmt::{Debug, Formatter, Write};
-use std::{mem, ptr};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+use std::ffi::{c_char, CStr, CString, OsStr};
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{mem, ptr, slice};
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Driver {
     Vaapi = 0,
     Nvidia = 1,
 }
 
+/// Classifies errors returned by the C interface and reported in diagnostics.
+///
+/// `NoDevice`, `InsufficientDriver`, `DriverMismatch`, `DeviceUnavailable` and `InitFailed`
+/// report why the driver couldn't be initialized. A later scan tries to initialize it again.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum ErrorCode {
+    CriticalError = -666,
+    Success = 0,
+    DriverFailure = 1,
+    OperationFailed = 2,
+    ConversionFailed = 3,
+    /// The driver is installed, but doesn't find a device.
+    NoDevice = 4,
+    /// The installed driver is older than required.
+    InsufficientDriver = 5,
+    /// The loaded driver library doesn't match the kernel driver, e.g. after the driver was
+    /// upgraded without reloading the kernel module.
+    DriverMismatch = 6,
+    /// The device is busy or unavailable, e.g. because it runs in exclusive-process compute mode.
+    DeviceUnavailable = 7,
+    /// The driver failed to initialize for any other reason.
+    InitFailed = 8,
+    /// An NVML call failed, the native code is an `nvmlReturn_t`.
+    NvmlFailed = 9,
+    /// An Optical Flow SDK call failed, the native code is an `NV_OF_STATUS`.
+    OpticalFlowFailed = 10,
+}
+
 #[repr(C)]
-#[derive(Hash, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Hash, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Codec {
     Mpeg1 = 1,
     Mpeg2 = 2,
@@ -42,7 +78,7 @@ pub enum Codec {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Chroma {
     Monochrome = 0,
     Yuv420 = 420,
@@ -51,7 +87,7 @@ pub enum Chroma {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum ColorDepth {
     Bit8 = 8,
     Bit10 = 10,
@@ -59,17 +95,75 @@ pub enum ColorDepth {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum EncodeProfile {
+    /// A profile GUID reported by NVENC that isn't known to the bundled `nvEncodeAPI.h`, see
+    /// [EncodingSpec::profile_guid].
+    Other = -1,
+    /// NVENC selects the profile based on the encoder configuration.
+    AutoSelect = 0,
     Baseline = 1,
+    /// HEVC format range extensions, e.g. 4:4:4 encoding.
+    Frext = 4,
     Main = 10,
     Main10 = 11,
     High = 100,
+    ConstrainedHigh = 101,
+    ProgressiveHigh = 102,
     High10 = 110,
     High12 = 112,
+    /// H.264 stereo high profile for multi view coding.
+    Stereo = 128,
     High444 = 140,
 }
 
+/// NVENC presets, `P1` is the fastest and `P7` the one with the best quality. `Legacy` is any
+/// other preset reported by the driver, e.g. one of those removed in NVENC 12.0, and can only be
+/// told apart by its GUID.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum EncodePreset {
+    Legacy = 0,
+    P1 = 1,
+    P2 = 2,
+    P3 = 3,
+    P4 = 4,
+    P5 = 5,
+    P6 = 6,
+    P7 = 7,
+}
+
+/// The use case a preset configuration is tuned for, corresponds to `NV_ENC_TUNING_INFO`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum TuningInfo {
+    HighQuality = 1,
+    LowLatency = 2,
+    UltraLowLatency = 3,
+    Lossless = 4,
+    UltraHighQuality = 5,
+}
+
+/// Corresponds to `NV_ENC_PARAMS_RC_MODE`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum RateControlMode {
+    ConstQp = 0,
+    Vbr = 1,
+    Cbr = 2,
+}
+
+/// Corresponds to `NV_ENC_MULTI_PASS`, the first pass of two-pass encoding runs at either quarter
+/// or full resolution.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum MultiPass {
+    SinglePass = 0,
+    TwoPassQuarterResolution = 1,
+    TwoPassFullResolution = 2,
+}
+
+/// `presets` is null if the codec can't be encoded with NVENC.
 #[repr(C)]
 #[derive(Clone, Eq, PartialEq)]
 pub struct CodecDetails {
@@ -78,6 +172,10 @@ pub struct CodecDetails {
     num_decoding_specs: u32,
     encoding_specs: *mut EncodingSpec,
     num_encoding_specs: u32,
+    nvenc_caps: *mut NvencCaps,
+    presets: *mut NvencPreset,
+    num_presets: u32,
+    input_formats: PixelFormats,
 }
 
 impl CodecDetails {
@@ -91,69 +189,35 @@ impl CodecDetails {
             num_decoding_specs,
             encoding_specs,
             num_encoding_specs,
-        }
-    }
-
-    pub fn combine(
-        codec: Codec,
-        decoding: Option<CodecDetails>,
-        encoding: Option<CodecDetails>,
-    ) -> Self {
-        let (decoding_specs, num_decoding_specs) = if let Some(decoding) = decoding {
-            unsafe { decoding.into_raw_decoding_specs() }
-        } else {
-            (ptr::null_mut(), 0)
-        };
-
-        let (encoding_specs, num_encoding_specs) = if let Some(encoding) = encoding {
-            unsafe { encoding.into_raw_encoding_specs() }
-        } else {
-            (ptr::null_mut(), 0)
-        };
-
-        Self {
-            codec,
-            decoding_specs,
-            num_decoding_specs,
-            encoding_specs,
-            num_encoding_specs,
+            nvenc_caps: ptr::null_mut(),
+            presets: ptr::null_mut(),
+            num_presets: 0,
+            input_formats: PixelFormats::default(),
         }
     }
 
     pub fn codec(&self) -> Codec {
         self.codec
     }
+}
 
-    unsafe fn into_raw_decoding_specs(self) -> (*mut DecodingSpec, u32) {
-        let Self {
-            decoding_specs,
-            num_decoding_specs,
-            ..
-        } = self;
+impl From<owned::CodecDetails> for CodecDetails {
+    fn from(value: owned::CodecDetails) -> Self {
+        let mut details =
+            CodecDetails::new(value.codec, value.decoding_specs, value.encoding_specs);
 
-        if !self.encoding_specs.is_null() {
-            drop_vec(self.encoding_specs, self.num_encoding_specs);
+        if let Some(nvenc_caps) = value.nvenc_caps {
+            details.nvenc_caps = Box::into_raw(Box::new(NvencCaps::from(nvenc_caps)));
         }
 
-        mem::forget(self);
-
-        (decoding_specs, num_decoding_specs)
-    }
-
-    unsafe fn into_raw_encoding_specs(self) -> (*mut EncodingSpec, u32) {
-        let Self {
-            encoding_specs,
-            num_encoding_specs,
-            ..
-        } = self;
+        details.input_formats = value.input_formats;
 
-        if !self.decoding_specs.is_null() {
-            drop_vec(self.decoding_specs, self.num_decoding_specs);
+        if let Some(presets) = value.presets {
+            (details.presets, details.num_presets) =
+                vec_to_ptr(presets.into_iter().map(NvencPreset::from).collect());
         }
 
-        mem::forget(self);
-
-        (encoding_specs, num_encoding_specs)
+        details
     }
 }
 
@@ -161,6 +225,14 @@ impl Drop for CodecDetails {
     fn drop(&mut self) {
         drop_vec(self.decoding_specs, self.num_decoding_specs);
         drop_vec(self.encoding_specs, self.num_encoding_specs);
+
+        if !self.nvenc_caps.is_null() {
+            unsafe {
+                let _ = Box::from_raw(self.nvenc_caps);
+            }
+        }
+
+        drop_vec(self.presets, self.num_presets);
     }
 }
 
@@ -205,6 +277,256 @@ impl Debug for CodecDetails {
 
         write!(f, "  encoding_specs: ")?;
         write_slice!(f, self.encoding_specs, self.num_encoding_specs)?;
+        writeln!(f, ",")?;
+
+        write!(f, "  nvenc_caps: ")?;
+
+        match unsafe { self.nvenc_caps.as_ref() } {
+            Some(nvenc_caps) => {
+                writeln!(f, "{}", format!("{:?}", nvenc_caps).replace('\n', "\n  "))?
+            }
+            None => writeln!(f, "null,")?,
+        }
+
+        write!(f, "  presets: ")?;
+        write_slice!(f, self.presets, self.num_presets)?;
+        writeln!(f, ",")?;
+
+        writeln!(
+            f,
+            "  input_formats: {:?}",
+            self.input_formats.iter().collect::<Vec<_>>()
+        )?;
+
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// All capabilities NVENC reports for a codec, see `NV_ENC_CAPS` in `nvEncodeAPI.h` for details.
+///
+/// Flags are `Unknown` and numeric values are `-1` if the driver doesn't support querying them.
+/// `raw_caps` contains every value the driver reported, including capabilities this library
+/// doesn't know about yet.
+#[repr(C)]
+#[derive(Clone, Eq, PartialEq)]
+pub struct NvencCaps {
+    num_max_bframes: i32,
+    supported_ratecontrol_modes: i32,
+    support_field_encoding: i32,
+    support_monochrome: ThreeValue,
+    support_fmo: ThreeValue,
+    support_qpelmv: ThreeValue,
+    support_bdirect_mode: ThreeValue,
+    support_cabac: ThreeValue,
+    support_adaptive_transform: ThreeValue,
+    support_stereo_mvc: ThreeValue,
+    num_max_temporal_layers: i32,
+    support_hierarchical_pframes: ThreeValue,
+    support_hierarchical_bframes: ThreeValue,
+    level_max: i32,
+    level_min: i32,
+    separate_colour_plane: ThreeValue,
+    width_max: i32,
+    height_max: i32,
+    support_temporal_svc: ThreeValue,
+    support_dyn_res_change: ThreeValue,
+    support_dyn_bitrate_change: ThreeValue,
+    support_dyn_force_constqp: ThreeValue,
+    support_dyn_rcmode_change: ThreeValue,
+    support_subframe_readback: ThreeValue,
+    support_constrained_encoding: ThreeValue,
+    support_intra_refresh: ThreeValue,
+    support_custom_vbv_buf_size: ThreeValue,
+    support_dynamic_slice_mode: ThreeValue,
+    support_ref_pic_invalidation: ThreeValue,
+    preproc_support: i32,
+    async_encode_support: ThreeValue,
+    mb_num_max: i32,
+    mb_per_sec_max: i32,
+    support_yuv444_encode: ThreeValue,
+    support_lossless_encode: ThreeValue,
+    support_sao: ThreeValue,
+    support_meonly_mode: ThreeValue,
+    support_lookahead: ThreeValue,
+    support_temporal_aq: ThreeValue,
+    support_10bit_encode: ThreeValue,
+    num_max_ltr_frames: i32,
+    support_weighted_prediction: ThreeValue,
+    dynamic_query_encoder_capacity: i32,
+    support_bframe_ref_mode: i32,
+    support_emphasis_level_map: ThreeValue,
+    width_min: i32,
+    height_min: i32,
+    support_multiple_ref_frames: ThreeValue,
+    support_alpha_layer_encoding: ThreeValue,
+    num_encoder_engines: i32,
+    single_slice_intra_refresh: ThreeValue,
+    disable_enc_state_advance: ThreeValue,
+    output_recon_surface: ThreeValue,
+    output_block_stats: ThreeValue,
+    output_row_stats: ThreeValue,
+    support_temporal_filter: ThreeValue,
+    support_lookahead_level: ThreeValue,
+    support_unidirectional_b: ThreeValue,
+    raw_caps: *mut NvencCapValue,
+    num_raw_caps: u32,
+}
+
+impl From<owned::NvencCaps> for NvencCaps {
+    fn from(value: owned::NvencCaps) -> Self {
+        let (raw_caps, num_raw_caps) = vec_to_ptr(
+            value
+                .raw
+                .into_iter()
+                .map(|(cap, value)| NvencCapValue { cap, value })
+                .collect(),
+        );
+
+        Self {
+            num_max_bframes: to_i32(value.num_max_bframes),
+            supported_ratecontrol_modes: to_i32(value.supported_ratecontrol_modes),
+            support_field_encoding: to_i32(value.support_field_encoding),
+            support_monochrome: value.support_monochrome.into(),
+            support_fmo: value.support_fmo.into(),
+            support_qpelmv: value.support_qpelmv.into(),
+            support_bdirect_mode: value.support_bdirect_mode.into(),
+            support_cabac: value.support_cabac.into(),
+            support_adaptive_transform: value.support_adaptive_transform.into(),
+            support_stereo_mvc: value.support_stereo_mvc.into(),
+            num_max_temporal_layers: to_i32(value.num_max_temporal_layers),
+            support_hierarchical_pframes: value.support_hierarchical_pframes.into(),
+            support_hierarchical_bframes: value.support_hierarchical_bframes.into(),
+            level_max: to_i32(value.level_max),
+            level_min: to_i32(value.level_min),
+            separate_colour_plane: value.separate_colour_plane.into(),
+            width_max: to_i32(value.width_max),
+            height_max: to_i32(value.height_max),
+            support_temporal_svc: value.support_temporal_svc.into(),
+            support_dyn_res_change: value.support_dyn_res_change.into(),
+            support_dyn_bitrate_change: value.support_dyn_bitrate_change.into(),
+            support_dyn_force_constqp: value.support_dyn_force_constqp.into(),
+            support_dyn_rcmode_change: value.support_dyn_rcmode_change.into(),
+            support_subframe_readback: value.support_subframe_readback.into(),
+            support_constrained_encoding: value.support_constrained_encoding.into(),
+            support_intra_refresh: value.support_intra_refresh.into(),
+            support_custom_vbv_buf_size: value.support_custom_vbv_buf_size.into(),
+            support_dynamic_slice_mode: value.support_dynamic_slice_mode.into(),
+            support_ref_pic_invalidation: value.support_ref_pic_invalidation.into(),
+            preproc_support: to_i32(value.preproc_support),
+            async_encode_support: value.async_encode_support.into(),
+            mb_num_max: to_i32(value.mb_num_max),
+            mb_per_sec_max: to_i32(value.mb_per_sec_max),
+            support_yuv444_encode: value.support_yuv444_encode.into(),
+            support_lossless_encode: value.support_lossless_encode.into(),
+            support_sao: value.support_sao.into(),
+            support_meonly_mode: value.support_meonly_mode.into(),
+            support_lookahead: value.support_lookahead.into(),
+            support_temporal_aq: value.support_temporal_aq.into(),
+            support_10bit_encode: value.support_10bit_encode.into(),
+            num_max_ltr_frames: to_i32(value.num_max_ltr_frames),
+            support_weighted_prediction: value.support_weighted_prediction.into(),
+            dynamic_query_encoder_capacity: to_i32(value.dynamic_query_encoder_capacity),
+            support_bframe_ref_mode: to_i32(value.support_bframe_ref_mode),
+            support_emphasis_level_map: value.support_emphasis_level_map.into(),
+            width_min: to_i32(value.width_min),
+            height_min: to_i32(value.height_min),
+            support_multiple_ref_frames: value.support_multiple_ref_frames.into(),
+            support_alpha_layer_encoding: value.support_alpha_layer_encoding.into(),
+            num_encoder_engines: to_i32(value.num_encoder_engines),
+            single_slice_intra_refresh: value.single_slice_intra_refresh.into(),
+            disable_enc_state_advance: value.disable_enc_state_advance.into(),
+            output_recon_surface: value.output_recon_surface.into(),
+            output_block_stats: value.output_block_stats.into(),
+            output_row_stats: value.output_row_stats.into(),
+            support_temporal_filter: value.support_temporal_filter.into(),
+            support_lookahead_level: value.support_lookahead_level.into(),
+            support_unidirectional_b: value.support_unidirectional_b.into(),
+            raw_caps,
+            num_raw_caps,
+        }
+    }
+}
+
+impl Drop for NvencCaps {
+    fn drop(&mut self) {
+        drop_vec(self.raw_caps, self.num_raw_caps);
+    }
+}
+
+impl Debug for NvencCaps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        macro_rules! write_fields {
+            ($($field:ident),*) => {
+                $(writeln!(f, "  {}: {:?},", stringify!($field), self.$field)?;)*
+            };
+        }
+
+        writeln!(f, "NvencCaps {{")?;
+        write_fields!(
+            num_max_bframes,
+            supported_ratecontrol_modes,
+            support_field_encoding,
+            support_monochrome,
+            support_fmo,
+            support_qpelmv,
+            support_bdirect_mode,
+            support_cabac,
+            support_adaptive_transform,
+            support_stereo_mvc,
+            num_max_temporal_layers,
+            support_hierarchical_pframes,
+            support_hierarchical_bframes,
+            level_max,
+            level_min,
+            separate_colour_plane,
+            width_max,
+            height_max,
+            support_temporal_svc,
+            support_dyn_res_change,
+            support_dyn_bitrate_change,
+            support_dyn_force_constqp,
+            support_dyn_rcmode_change,
+            support_subframe_readback,
+            support_constrained_encoding,
+            support_intra_refresh,
+            support_custom_vbv_buf_size,
+            support_dynamic_slice_mode,
+            support_ref_pic_invalidation,
+            preproc_support,
+            async_encode_support,
+            mb_num_max,
+            mb_per_sec_max,
+            support_yuv444_encode,
+            support_lossless_encode,
+            support_sao,
+            support_meonly_mode,
+            support_lookahead,
+            support_temporal_aq,
+            support_10bit_encode,
+            num_max_ltr_frames,
+            support_weighted_prediction,
+            dynamic_query_encoder_capacity,
+            support_bframe_ref_mode,
+            support_emphasis_level_map,
+            width_min,
+            height_min,
+            support_multiple_ref_frames,
+            support_alpha_layer_encoding,
+            num_encoder_engines,
+            single_slice_intra_refresh,
+            disable_enc_state_advance,
+            output_recon_surface,
+            output_block_stats,
+            output_row_stats,
+            support_temporal_filter,
+            support_lookahead_level,
+            support_unidirectional_b
+        );
+
+        write!(f, "  raw_caps: ")?;
+        write_slice!(f, self.raw_caps, self.num_raw_caps)?;
         writeln!(f)?;
 
         write!(f, "}}")?;
@@ -213,17 +535,279 @@ impl Debug for CodecDetails {
     }
 }
 
+/// A single capability value as returned by `nvEncGetEncodeCaps`, `cap` is the `NV_ENC_CAPS`
+/// value that was queried.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NvencCapValue {
+    pub cap: u32,
+    pub value: i32,
+}
+
+/// A preset NVENC supports for a codec, `guid` is the preset GUID reported by the driver.
+///
+/// `configs` contains the default configuration of the preset for every tuning info the driver
+/// accepts, it's empty if the negotiated NVENC API version is older than 12.2.
+#[repr(C)]
+#[derive(Clone, Eq, PartialEq)]
+pub struct NvencPreset {
+    preset: EncodePreset,
+    guid: [u8; 16],
+    configs: *mut PresetConfig,
+    num_configs: u32,
+}
+
+impl From<owned::NvencPreset> for NvencPreset {
+    fn from(value: owned::NvencPreset) -> Self {
+        let (configs, num_configs) = vec_to_ptr(value.configs);
+
+        Self {
+            preset: value.preset,
+            guid: value.guid.into_bytes(),
+            configs,
+            num_configs,
+        }
+    }
+}
+
+impl Drop for NvencPreset {
+    fn drop(&mut self) {
+        drop_vec(self.configs, self.num_configs);
+    }
+}
+
+impl Debug for NvencPreset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "NvencPreset {{")?;
+        writeln!(f, "  preset: {:?},", self.preset)?;
+        writeln!(f, "  guid: {},", Uuid::from_bytes(self.guid))?;
+
+        write!(f, "  configs: ")?;
+        write_slice!(f, self.configs, self.num_configs)?;
+        writeln!(f)?;
+
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// Default encoder configuration of a preset for one tuning info, as returned by
+/// `nvEncGetEncodePresetConfigEx`.
+///
+/// `gop_length` is `u32::MAX` for an infinite GOP. `frame_interval_p` is the distance between two
+/// P-frames, i.e. `1` means no B-frames. Bitrates are specified in bits per second, `0` lets the
+/// driver choose.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetConfig {
+    pub tuning_info: TuningInfo,
+    pub gop_length: u32,
+    pub frame_interval_p: i32,
+    pub rate_control_mode: RateControlMode,
+    pub multi_pass: MultiPass,
+    pub lookahead: bool,
+    pub lookahead_depth: u16,
+    pub adaptive_quantization: bool,
+    pub temporal_adaptive_quantization: bool,
+    pub average_bitrate: u32,
+    pub max_bitrate: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DecodingSpec {
     pub chroma: Chroma,
     pub color_depth: ColorDepth,
     pub max_width: u32,
     pub max_height: u32,
+    /// Minimum supported width, `0` if unknown.
+    #[serde(default)]
+    pub min_width: u32,
+    /// Minimum supported height, `0` if unknown.
+    #[serde(default)]
+    pub min_height: u32,
+    /// Maximum number of macroblocks per frame, `0` if unknown.
+    #[serde(default)]
+    pub max_mb_count: u32,
+    /// Surface formats the decoder can output without a separate conversion, empty if unknown.
+    #[serde(default)]
+    pub output_formats: OutputFormats,
+    /// Whether the decoder can collect histogram data of the decoded frames, unknown if the driver
+    /// doesn't report it.
+    #[serde(default)]
+    pub histogram_supported: ThreeValue,
+    /// Bit depth of the histogram counters, `0` if unknown.
+    #[serde(default)]
+    pub counter_bit_depth: u8,
+    /// Maximum number of histogram bins, `0` if unknown.
+    #[serde(default)]
+    pub max_histogram_bins: u16,
 }
 
+/// Surface format a decoder writes its output to, corresponds to `cudaVideoSurfaceFormat`.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum OutputFormat {
+    /// Semi-planar 8-bit YUV 4:2:0
+    Nv12 = 0,
+    /// Semi-planar 16-bit YUV 4:2:0
+    P016 = 1,
+    /// Planar 8-bit YUV 4:4:4
+    Yuv444Planar = 2,
+    /// Planar 16-bit YUV 4:4:4
+    Yuv444Planar16 = 3,
+}
+
+impl OutputFormat {
+    const ALL: [OutputFormat; 4] = [
+        OutputFormat::Nv12,
+        OutputFormat::P016,
+        OutputFormat::Yuv444Planar,
+        OutputFormat::Yuv444Planar16,
+    ];
+}
+
+/// Bitmask of [OutputFormat] values, bit `n` is set if the format with value `n` is supported.
+///
+/// Serialized as an array of [OutputFormat].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OutputFormats(u32);
+
+impl OutputFormats {
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, format: OutputFormat) -> bool {
+        self.0 & (1 << format as u32) != 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = OutputFormat> + '_ {
+        OutputFormat::ALL
+            .into_iter()
+            .filter(|&format| self.contains(format))
+    }
+}
+
+impl FromIterator<OutputFormat> for OutputFormats {
+    fn from_iter<T: IntoIterator<Item = OutputFormat>>(iter: T) -> Self {
+        Self(iter.into_iter().fold(0, |bits, it| bits | (1 << it as u32)))
+    }
+}
+
+impl Serialize for OutputFormats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputFormats {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<OutputFormat>::deserialize(deserializer).map(OutputFormats::from_iter)
+    }
+}
+
+/// Buffer format an encoder accepts as input, corresponds to `NV_ENC_BUFFER_FORMAT`.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum PixelFormat {
+    /// Semi-planar 8-bit YUV 4:2:0
+    Nv12 = 0,
+    /// Planar 8-bit YUV 4:2:0, V plane before U plane
+    Yv12 = 1,
+    /// Planar 8-bit YUV 4:2:0, U plane before V plane
+    Iyuv = 2,
+    /// Planar 8-bit YUV 4:4:4
+    Yuv444 = 3,
+    /// Semi-planar 10-bit YUV 4:2:0 in 16-bit words
+    P010 = 4,
+    /// Planar 10-bit YUV 4:4:4 in 16-bit words
+    Yuv444Bit10 = 5,
+    /// Packed 8-bit A8R8G8B8
+    Argb = 6,
+    /// Packed 10-bit A2R10G10B10
+    Argb10 = 7,
+    /// Packed 8-bit A8Y8U8V8
+    Ayuv = 8,
+    /// Packed 8-bit A8B8G8R8
+    Abgr = 9,
+    /// Packed 10-bit A2B10G10R10
+    Abgr10 = 10,
+}
+
+impl PixelFormat {
+    const ALL: [PixelFormat; 11] = [
+        PixelFormat::Nv12,
+        PixelFormat::Yv12,
+        PixelFormat::Iyuv,
+        PixelFormat::Yuv444,
+        PixelFormat::P010,
+        PixelFormat::Yuv444Bit10,
+        PixelFormat::Argb,
+        PixelFormat::Argb10,
+        PixelFormat::Ayuv,
+        PixelFormat::Abgr,
+        PixelFormat::Abgr10,
+    ];
+}
+
+/// Bitmask of [PixelFormat] values, bit `n` is set if the format with value `n` is supported.
+///
+/// Serialized as an array of [PixelFormat].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PixelFormats(u32);
+
+impl PixelFormats {
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, format: PixelFormat) -> bool {
+        self.0 & (1 << format as u32) != 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PixelFormat> + '_ {
+        PixelFormat::ALL
+            .into_iter()
+            .filter(|&format| self.contains(format))
+    }
+}
+
+impl FromIterator<PixelFormat> for PixelFormats {
+    fn from_iter<T: IntoIterator<Item = PixelFormat>>(iter: T) -> Self {
+        Self(iter.into_iter().fold(0, |bits, it| bits | (1 << it as u32)))
+    }
+}
+
+impl Serialize for PixelFormats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for PixelFormats {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<PixelFormat>::deserialize(deserializer).map(PixelFormats::from_iter)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct EncodingSpec {
     pub chroma: Chroma,
     pub color_depth: ColorDepth,
@@ -231,13 +815,56 @@ pub struct EncodingSpec {
     pub max_width: u32,
     pub max_height: u32,
     pub b_frames_supported: ThreeValue,
+    /// The profile GUID reported by NVENC, all zeroes if the profile wasn't reported by NVENC.
+    ///
+    /// Serialized as a UUID string, or `null` if all zeroes.
+    #[serde(default, with = "guid_bytes")]
+    pub profile_guid: [u8; 16],
+}
+
+mod guid_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 16], serializer: S) -> Result<S::Ok, S::Error> {
+        Some(Uuid::from_bytes(*bytes))
+            .filter(|it| !it.is_nil())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 16], D::Error> {
+        Option::<Uuid>::deserialize(deserializer).map(|it| it.unwrap_or_default().into_bytes())
+    }
+}
+
+/// Describes whether the encoding capabilities of a device could be probed during the scan.
+///
+/// A GPU is busy if all of its encode sessions are in use, or if it runs in exclusive-process
+/// compute mode and another process holds its context. The latter also prevents probing its
+/// decoding capabilities.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum EncoderStatus {
+    /// All capabilities were probed during this scan.
+    #[default]
+    Available = 0,
+    /// The device was busy, the capabilities that couldn't be probed are taken from the last
+    /// successful scan of the device in this process.
+    Cached = 1,
+    /// The device was busy and hasn't been scanned successfully before, the capabilities that
+    /// couldn't be probed are missing.
+    Busy = 2,
+    /// The driver is older than NVENC 7.0, the oldest API version this library supports, so the
+    /// encoding capabilities weren't probed.
+    UnsupportedApi = 3,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
 pub enum ThreeValue {
     False = 0,
     True = 1,
+    #[default]
     Unknown = 2,
 }
 
@@ -251,6 +878,305 @@ impl From<bool> for ThreeValue {
     }
 }
 
+impl From<ThreeValue> for Option<bool> {
+    fn from(value: ThreeValue) -> Self {
+        match value {
+            ThreeValue::False => Some(false),
+            ThreeValue::True => Some(true),
+            ThreeValue::Unknown => None,
+        }
+    }
+}
+
+impl From<Option<bool>> for ThreeValue {
+    fn from(value: Option<bool>) -> Self {
+        value.map(ThreeValue::from).unwrap_or(ThreeValue::Unknown)
+    }
+}
+
+/// Serialized as `true`, `false` or `null`.
+impl Serialize for ThreeValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Option::<bool>::from(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ThreeValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<bool>::deserialize(deserializer).map(ThreeValue::from)
+    }
+}
+
+/// Version of a driver API, e.g. `12.2` for NVENC. `0.0` means the version is unknown or the API
+/// isn't used by the device.
+#[repr(C)]
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize, Deserialize,
+)]
+pub struct ApiVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ApiVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl Display for ApiVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// PCI location of a device, formatted as `domain:bus:device.function` in hexadecimal, e.g.
+/// `0000:65:00.0`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct PciAddress {
+    pub domain: u32,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Display for PciAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{:x}",
+            self.domain, self.bus, self.device, self.function
+        )
+    }
+}
+
+impl FromStr for PciAddress {
+    type Err = String;
+
+    /// Parses addresses like `0000:65:00.0` as returned by `cuDeviceGetPCIBusId`, the domain may
+    /// also be 8 digits long like in the output of `nvidia-smi`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid PCI address: {}", s);
+
+        let (domain, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (bus, rest) = rest.split_once(':').ok_or_else(invalid)?;
+        let (device, function) = rest.split_once('.').ok_or_else(invalid)?;
+
+        Ok(Self {
+            domain: u32::from_str_radix(domain, 16).map_err(|_| invalid())?,
+            bus: u8::from_str_radix(bus, 16).map_err(|_| invalid())?,
+            device: u8::from_str_radix(device, 16).map_err(|_| invalid())?,
+            function: u8::from_str_radix(function, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Serialized as a string like `0000:65:00.0`.
+impl Serialize for PciAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PciAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Properties of a CUDA device. `uuid` is all zeros if the driver doesn't report one.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CudaDeviceInfo {
+    pub uuid: [u8; 16],
+    pub pci_address: PciAddress,
+    /// Compute capability of the device, e.g. `8.6`.
+    pub compute_capability: ApiVersion,
+    /// Total memory in bytes.
+    pub total_memory: u64,
+    pub multiprocessor_count: u32,
+    pub integrated: bool,
+    pub tcc_driver: bool,
+    /// Version of the CUDA driver API, e.g. `12.4`.
+    pub driver_version: ApiVersion,
+    /// Index of the device in NVML and `nvidia-smi`, `-1` if NVML isn't available. Unlike the
+    /// ordinal of the device it isn't remapped by `CUDA_VISIBLE_DEVICES`.
+    pub nvml_index: i32,
+}
+
+impl From<owned::CudaDeviceInfo> for CudaDeviceInfo {
+    fn from(value: owned::CudaDeviceInfo) -> Self {
+        Self {
+            uuid: value.uuid.map(|it| it.into_bytes()).unwrap_or_default(),
+            pci_address: value.pci_address,
+            compute_capability: value.compute_capability,
+            total_memory: value.total_memory,
+            multiprocessor_count: value.multiprocessor_count,
+            integrated: value.integrated,
+            tcc_driver: value.tcc_driver,
+            driver_version: value.driver_version,
+            nvml_index: to_i32(value.nvml_index),
+        }
+    }
+}
+
+/// Runtime telemetry of a device as reported by NVML. Values are `-1` if the driver doesn't
+/// report them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Telemetry {
+    /// GPU utilization in percent.
+    pub gpu_utilization: i32,
+    /// Encoder utilization in percent.
+    pub encoder_utilization: i32,
+    /// Decoder utilization in percent.
+    pub decoder_utilization: i32,
+    /// Number of active encoder sessions.
+    pub encoder_sessions: i32,
+    /// Average frames per second of all active encoder sessions.
+    pub encoder_average_fps: i32,
+    /// Average latency of all active encoder sessions in microseconds.
+    pub encoder_average_latency: i32,
+    /// GPU temperature in degrees Celsius.
+    pub temperature: i32,
+    /// Power usage in milliwatts.
+    pub power_usage: i32,
+    /// Total memory in bytes.
+    pub memory_total: i64,
+    /// Used memory in bytes.
+    pub memory_used: i64,
+}
+
+impl From<owned::Telemetry> for Telemetry {
+    fn from(value: owned::Telemetry) -> Self {
+        Self {
+            gpu_utilization: to_i32(value.gpu_utilization),
+            encoder_utilization: to_i32(value.encoder_utilization),
+            decoder_utilization: to_i32(value.decoder_utilization),
+            encoder_sessions: to_i32(value.encoder_sessions),
+            encoder_average_fps: to_i32(value.encoder_average_fps),
+            encoder_average_latency: to_i32(value.encoder_average_latency),
+            temperature: to_i32(value.temperature),
+            power_usage: to_i32(value.power_usage),
+            memory_total: to_i64(value.memory_total),
+            memory_used: to_i64(value.memory_used),
+        }
+    }
+}
+
+fn to_i32(value: Option<u32>) -> i32 {
+    value.and_then(|it| it.try_into().ok()).unwrap_or(-1)
+}
+
+fn to_i64(value: Option<u64>) -> i64 {
+    value.and_then(|it| it.try_into().ok()).unwrap_or(-1)
+}
+
+/// Capabilities of the optical flow accelerator of a device, see `NV_OF_CAPS` in the Optical Flow
+/// SDK for details.
+///
+/// Grid sizes are specified in pixels, `hint_grid_sizes` is empty if external hints aren't
+/// supported. `max_roi_count` is `0` if regions of interest aren't supported.
+#[repr(C)]
+#[derive(Clone, Eq, PartialEq)]
+pub struct OpticalFlowCaps {
+    output_grid_sizes: *mut u32,
+    num_output_grid_sizes: u32,
+    hint_grid_sizes: *mut u32,
+    num_hint_grid_sizes: u32,
+    hint_with_optical_flow: bool,
+    hint_with_stereo_disparity: bool,
+    min_width: u32,
+    min_height: u32,
+    max_width: u32,
+    max_height: u32,
+    roi_supported: bool,
+    max_roi_count: u32,
+}
+
+impl From<owned::OpticalFlowCaps> for OpticalFlowCaps {
+    fn from(value: owned::OpticalFlowCaps) -> Self {
+        let (output_grid_sizes, num_output_grid_sizes) = vec_to_ptr(value.output_grid_sizes);
+        let (hint_grid_sizes, num_hint_grid_sizes) = vec_to_ptr(value.hint_grid_sizes);
+
+        Self {
+            output_grid_sizes,
+            num_output_grid_sizes,
+            hint_grid_sizes,
+            num_hint_grid_sizes,
+            hint_with_optical_flow: value.hint_with_optical_flow,
+            hint_with_stereo_disparity: value.hint_with_stereo_disparity,
+            min_width: value.min_width,
+            min_height: value.min_height,
+            max_width: value.max_width,
+            max_height: value.max_height,
+            roi_supported: value.roi_supported,
+            max_roi_count: value.max_roi_count,
+        }
+    }
+}
+
+impl Drop for OpticalFlowCaps {
+    fn drop(&mut self) {
+        drop_vec(self.output_grid_sizes, self.num_output_grid_sizes);
+        drop_vec(self.hint_grid_sizes, self.num_hint_grid_sizes);
+    }
+}
+
+impl Debug for OpticalFlowCaps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "OpticalFlowCaps {{")?;
+
+        write!(f, "  output_grid_sizes: ")?;
+        write_slice!(f, self.output_grid_sizes, self.num_output_grid_sizes)?;
+        writeln!(f, ",")?;
+
+        write!(f, "  hint_grid_sizes: ")?;
+        write_slice!(f, self.hint_grid_sizes, self.num_hint_grid_sizes)?;
+        writeln!(f, ",")?;
+
+        writeln!(
+            f,
+            "  hint_with_optical_flow: {},",
+            self.hint_with_optical_flow
+        )?;
+        writeln!(
+            f,
+            "  hint_with_stereo_disparity: {},",
+            self.hint_with_stereo_disparity
+        )?;
+        writeln!(f, "  min_width: {},", self.min_width)?;
+        writeln!(f, "  min_height: {},", self.min_height)?;
+        writeln!(f, "  max_width: {},", self.max_width)?;
+        writeln!(f, "  max_height: {},", self.max_height)?;
+        writeln!(f, "  roi_supported: {},", self.roi_supported)?;
+        writeln!(f, "  max_roi_count: {}", self.max_roi_count)?;
+
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+impl From<CudaDeviceInfo> for owned::CudaDeviceInfo {
+    fn from(value: CudaDeviceInfo) -> Self {
+        Self {
+            uuid: Some(Uuid::from_bytes(value.uuid)).filter(|it| !it.is_nil()),
+            pci_address: value.pci_address,
+            compute_capability: value.compute_capability,
+            total_memory: value.total_memory,
+            multiprocessor_count: value.multiprocessor_count,
+            integrated: value.integrated,
+            tcc_driver: value.tcc_driver,
+            driver_version: value.driver_version,
+            nvml_index: value.nvml_index.try_into().ok(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Eq, PartialEq)]
 pub struct Device {
@@ -260,6 +1186,12 @@ pub struct Device {
     name: *mut c_char,
     codecs: *mut CodecDetails,
     num_codecs: u32,
+    nvenc_api_version: ApiVersion,
+    cuda: *mut CudaDeviceInfo,
+    telemetry: *mut Telemetry,
+    max_concurrent_sessions: i32,
+    encoder_status: EncoderStatus,
+    optical_flow: *mut OpticalFlowCaps,
 }
 
 impl Device {
@@ -279,6 +1211,12 @@ impl Device {
             name: name.into_raw(),
             codecs,
             num_codecs,
+            nvenc_api_version: ApiVersion::default(),
+            cuda: ptr::null_mut(),
+            telemetry: ptr::null_mut(),
+            max_concurrent_sessions: -1,
+            encoder_status: EncoderStatus::Available,
+            optical_flow: ptr::null_mut(),
         }
     }
 
@@ -299,7 +1237,70 @@ impl Device {
             name: name.map(|it| it.into_raw()).unwrap_or(ptr::null_mut()),
             codecs,
             num_codecs,
+            nvenc_api_version: ApiVersion::default(),
+            cuda: ptr::null_mut(),
+            telemetry: ptr::null_mut(),
+            max_concurrent_sessions: -1,
+            encoder_status: EncoderStatus::Available,
+            optical_flow: ptr::null_mut(),
+        }
+    }
+}
+
+impl Device {
+    /// Returns the properties of the CUDA device, `None` for devices not using CUDA.
+    pub fn cuda(&self) -> Option<&CudaDeviceInfo> {
+        unsafe { self.cuda.as_ref() }
+    }
+
+    /// Replaces the telemetry of the device, releasing the previous one.
+    pub fn set_telemetry(&mut self, telemetry: Option<owned::Telemetry>) {
+        if !self.telemetry.is_null() {
+            unsafe {
+                let _ = Box::from_raw(self.telemetry);
+            }
         }
+
+        self.telemetry = telemetry
+            .map(|it| Box::into_raw(Box::new(Telemetry::from(it))))
+            .unwrap_or(ptr::null_mut());
+    }
+}
+
+impl From<owned::Device> for Device {
+    fn from(value: owned::Device) -> Self {
+        let codecs = value.codecs.into_iter().map(CodecDetails::from).collect();
+
+        let mut device = match value.path {
+            Some(path) => Device::new_with_path(
+                value.driver,
+                path.to_string_lossy().to_string(),
+                value.name,
+                codecs,
+            ),
+            None => Device::new_with_ordinal(
+                value.driver,
+                value.ordinal.unwrap_or_default(),
+                value.name.unwrap_or_default(),
+                codecs,
+            ),
+        };
+
+        device.nvenc_api_version = value.nvenc_api_version.unwrap_or_default();
+
+        if let Some(cuda) = value.cuda {
+            device.cuda = Box::into_raw(Box::new(CudaDeviceInfo::from(cuda)));
+        }
+
+        device.set_telemetry(value.telemetry);
+        device.max_concurrent_sessions = to_i32(value.max_concurrent_sessions);
+        device.encoder_status = value.encoder_status;
+
+        if let Some(optical_flow) = value.optical_flow {
+            device.optical_flow = Box::into_raw(Box::new(OpticalFlowCaps::from(optical_flow)));
+        }
+
+        device
     }
 }
 
@@ -316,6 +1317,20 @@ impl Drop for Device {
         }
 
         drop_vec(self.codecs, self.num_codecs);
+
+        if !self.cuda.is_null() {
+            unsafe {
+                let _ = Box::from_raw(self.cuda);
+            }
+        }
+
+        self.set_telemetry(None);
+
+        if !self.optical_flow.is_null() {
+            unsafe {
+                let _ = Box::from_raw(self.optical_flow);
+            }
+        }
     }
 }
 
@@ -344,7 +1359,167 @@ impl Debug for Device {
 
         write!(f, "  codecs: ")?;
         write_slice!(f, self.codecs, self.num_codecs)?;
-        writeln!(f)?;
+        writeln!(f, ",")?;
+
+        writeln!(f, "  nvenc_api_version: {},", self.nvenc_api_version)?;
+        writeln!(f, "  cuda: {:?},", unsafe { self.cuda.as_ref() })?;
+        writeln!(f, "  telemetry: {:?},", unsafe { self.telemetry.as_ref() })?;
+        writeln!(
+            f,
+            "  max_concurrent_sessions: {},",
+            self.max_concurrent_sessions
+        )?;
+        writeln!(f, "  encoder_status: {:?},", self.encoder_status)?;
+        writeln!(f, "  optical_flow: {:?}", unsafe {
+            self.optical_flow.as_ref()
+        })?;
+
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// Describes why a single device or a whole driver couldn't be scanned.
+///
+/// `ordinal` is `-1` and `path` is null if the failure isn't related to a specific device.
+/// `native_code` contains the status code returned by the driver API (e.g. `CUresult`,
+/// `NVENCSTATUS`, `VAStatus` or `errno`), or `0` if there is none.
+#[repr(C)]
+#[derive(Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    driver: Driver,
+    ordinal: i32,
+    path: *mut c_char,
+    kind: ErrorCode,
+    native_code: i64,
+    message: *mut c_char,
+}
+
+impl From<owned::Diagnostic> for Diagnostic {
+    fn from(value: owned::Diagnostic) -> Self {
+        let path = value
+            .path
+            .map(|it| CString::new(it.to_string_lossy().to_string()).unwrap());
+        let message = CString::new(value.message).unwrap();
+
+        Self {
+            driver: value.driver,
+            ordinal: value.ordinal.map(i32::from).unwrap_or(-1),
+            path: path.map(|it| it.into_raw()).unwrap_or(ptr::null_mut()),
+            kind: value.kind,
+            native_code: value.native_code.unwrap_or_default(),
+            message: message.into_raw(),
+        }
+    }
+}
+
+impl Drop for Diagnostic {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.path.is_null() {
+                let _ = CString::from_raw(self.path);
+            }
+
+            if !self.message.is_null() {
+                let _ = CString::from_raw(self.message);
+            }
+        }
+    }
+}
+
+impl Debug for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Diagnostic {{")?;
+        writeln!(f, "  driver: {:?},", self.driver)?;
+        writeln!(f, "  ordinal: {},", self.ordinal)?;
+        write!(f, "  path: ")?;
+
+        if !self.path.is_null() {
+            let str = unsafe { CStr::from_ptr(self.path as *const c_char) };
+            writeln!(f, "{:?},", str)?;
+        } else {
+            writeln!(f, "null,")?;
+        }
+
+        writeln!(f, "  kind: {:?},", self.kind)?;
+        writeln!(f, "  native_code: {},", self.native_code)?;
+        write!(f, "  message: ")?;
+
+        if !self.message.is_null() {
+            let str = unsafe { CStr::from_ptr(self.message as *const c_char) };
+            writeln!(f, "{:?}", str)?;
+        } else {
+            writeln!(f, "null")?;
+        }
+
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// A driver library a backend tried to load, `name` is the name it's usually installed under.
+///
+/// `path` is the file the library was loaded from and `error` is null if it was loaded
+/// successfully, otherwise `path` is null and `error` describes why it couldn't be loaded.
+#[repr(C)]
+#[derive(Clone, Eq, PartialEq)]
+pub struct LibraryInfo {
+    driver: Driver,
+    name: *mut c_char,
+    path: *mut c_char,
+    error: *mut c_char,
+}
+
+impl From<owned::LibraryInfo> for LibraryInfo {
+    fn from(value: owned::LibraryInfo) -> Self {
+        let name = CString::new(value.name).unwrap();
+        let path = value
+            .path
+            .map(|it| CString::new(it.to_string_lossy().to_string()).unwrap());
+        let error = value.error.map(|it| CString::new(it).unwrap());
+
+        Self {
+            driver: value.driver,
+            name: name.into_raw(),
+            path: path.map(|it| it.into_raw()).unwrap_or(ptr::null_mut()),
+            error: error.map(|it| it.into_raw()).unwrap_or(ptr::null_mut()),
+        }
+    }
+}
+
+impl Drop for LibraryInfo {
+    fn drop(&mut self) {
+        unsafe {
+            for str in [self.name, self.path, self.error] {
+                if !str.is_null() {
+                    let _ = CString::from_raw(str);
+                }
+            }
+        }
+    }
+}
+
+impl Debug for LibraryInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "LibraryInfo {{")?;
+        writeln!(f, "  driver: {:?},", self.driver)?;
+
+        for (name, str) in [
+            ("name", self.name),
+            ("path", self.path),
+            ("error", self.error),
+        ] {
+            write!(f, "  {}: ", name)?;
+
+            if !str.is_null() {
+                let str = unsafe { CStr::from_ptr(str as *const c_char) };
+                writeln!(f, "{:?},", str)?;
+            } else {
+                writeln!(f, "null,")?;
+            }
+        }
 
         write!(f, "}}")?;
 
@@ -357,22 +1532,60 @@ impl Debug for Device {
 pub struct EncDecDevices {
     devices: *mut Device,
     num_devices: u32,
+    diagnostics: *mut Diagnostic,
+    num_diagnostics: u32,
+    libraries: *mut LibraryInfo,
+    num_libraries: u32,
 }
 
 impl EncDecDevices {
-    pub fn new(devices: Vec<Device>) -> Self {
+    pub fn new(
+        devices: Vec<Device>,
+        diagnostics: Vec<Diagnostic>,
+        libraries: Vec<LibraryInfo>,
+    ) -> Self {
         let (devices, num_devices) = vec_to_ptr(devices);
+        let (diagnostics, num_diagnostics) = vec_to_ptr(diagnostics);
+        let (libraries, num_libraries) = vec_to_ptr(libraries);
 
         Self {
             devices,
             num_devices,
+            diagnostics,
+            num_diagnostics,
+            libraries,
+            num_libraries,
+        }
+    }
+
+    pub fn devices_mut(&mut self) -> &mut [Device] {
+        if self.devices.is_null() {
+            &mut []
+        } else {
+            unsafe { slice::from_raw_parts_mut(self.devices, self.num_devices as usize) }
         }
     }
 }
 
+impl From<owned::ScanResult> for EncDecDevices {
+    fn from(value: owned::ScanResult) -> Self {
+        EncDecDevices::new(
+            value.devices.into_iter().map(Device::from).collect(),
+            value
+                .diagnostics
+                .into_iter()
+                .map(Diagnostic::from)
+                .collect(),
+            value.libraries.into_iter().map(LibraryInfo::from).collect(),
+        )
+    }
+}
+
 impl Drop for EncDecDevices {
     fn drop(&mut self) {
         drop_vec(self.devices, self.num_devices);
+        drop_vec(self.diagnostics, self.num_diagnostics);
+        drop_vec(self.libraries, self.num_libraries);
     }
 }
 
@@ -382,6 +1595,14 @@ impl Debug for EncDecDevices {
 
         write!(f, "  devices: ")?;
         write_slice!(f, self.devices, self.num_devices)?;
+        writeln!(f, ",")?;
+
+        write!(f, "  diagnostics: ")?;
+        write_slice!(f, self.diagnostics, self.num_diagnostics)?;
+        writeln!(f, ",")?;
+
+        write!(f, "  libraries: ")?;
+        write_slice!(f, self.libraries, self.num_libraries)?;
         writeln!(f)?;
 
         writeln!(f, "}}")?;
@@ -389,3 +1610,140 @@ impl Debug for EncDecDevices {
         Ok(())
     }
 }
+
+/// Restricts which drivers, devices and codecs are probed by `scan_devices_with_options`.
+///
+/// `size` must be set to `sizeof(ScanOptions)`. Fields are only ever appended, so a caller built
+/// against an older header passes a smaller size and only the fields that fit into it are read,
+/// every other field keeps its default. A zero-initialized instance scans everything.
+///
+/// Lists with a count of `0` don't restrict anything. `probe_encoder_sessions` enables probing
+/// the number of concurrent NVENC sessions, which is reported as `max_concurrent_sessions` of each
+/// device (`-1` if not probed).
+/// `nvidia_library_dirs` are searched for the NVIDIA libraries before the directories known to
+/// the dynamic linker, they only take effect if passed to the first scan of the process.
+/// `nvidia_device_mask` selects NVIDIA devices with the syntax of `CUDA_VISIBLE_DEVICES` (NVML
+/// indices, UUIDs or PCI bus IDs), independent of how that variable remaps the CUDA ordinals.
+/// Integer entries are NVML indices, not CUDA ordinals, and match nothing without NVML.
+/// `use_primary_context` probes NVIDIA devices with their primary context instead of a context
+/// created for the scan, the current context of the calling thread is preserved either way.
+#[repr(C)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanOptions {
+    pub size: u32,
+    pub disable_nvidia: bool,
+    pub disable_vaapi: bool,
+    pub cuda_ordinals: *const u8,
+    pub num_cuda_ordinals: u32,
+    pub drm_paths: *const *const c_char,
+    pub num_drm_paths: u32,
+    pub codecs: *const Codec,
+    pub num_codecs: u32,
+    pub nvidia_library_dirs: *const *const c_char,
+    pub num_nvidia_library_dirs: u32,
+    pub probe_encoder_sessions: bool,
+    pub nvidia_device_mask: *const *const c_char,
+    pub num_nvidia_device_mask: u32,
+    pub use_primary_context: bool,
+}
+
+impl ScanOptions {
+    /// Copies the options into their owned counterpart, fields beyond `size` keep their default.
+    ///
+    /// # Safety
+    ///
+    /// `options` must point to at least `size` readable bytes. Every non-null list must point to
+    /// the specified number of valid elements, and every DRM path, library directory and device
+    /// mask entry must be a valid, nul-terminated string.
+    pub unsafe fn as_owned(options: *const ScanOptions) -> owned::ScanOptions {
+        let options = Self::read(options);
+
+        owned::ScanOptions {
+            nvidia: !options.disable_nvidia,
+            vaapi: !options.disable_vaapi,
+            cuda_ordinals: read_slice(options.cuda_ordinals, options.num_cuda_ordinals)
+                .map(|it| it.to_vec()),
+            drm_paths: read_slice(options.drm_paths, options.num_drm_paths)
+                .map(|it| read_paths(it)),
+            codecs: read_slice(options.codecs, options.num_codecs).map(|it| it.to_vec()),
+            probe_encoder_sessions: options.probe_encoder_sessions,
+            nvidia_library_dirs: read_slice(
+                options.nvidia_library_dirs,
+                options.num_nvidia_library_dirs,
+            )
+            .map(|it| read_paths(it))
+            .unwrap_or_default(),
+            nvidia_device_mask: read_slice(
+                options.nvidia_device_mask,
+                options.num_nvidia_device_mask,
+            )
+            .map(|it| read_strings(it)),
+            use_primary_context: options.use_primary_context,
+        }
+    }
+
+    /// Reads the fields of `options` that fit into its `size`. New fields must start at or past
+    /// the previous `size_of::<ScanOptions>()`, otherwise they'd be read from the padding of
+    /// older callers. On 64-bit targets the layouts so far end after `num_codecs` (56 bytes),
+    /// `probe_encoder_sessions` (72 bytes) and `use_primary_context` (88 bytes).
+    unsafe fn read(options: *const ScanOptions) -> ScanOptions {
+        let size = ptr::addr_of!((*options).size).read() as usize;
+        let mut result: ScanOptions = mem::zeroed();
+        result.size = mem::size_of::<ScanOptions>() as u32;
+
+        macro_rules! read_fields {
+            ($($field: ident),*) => {
+                $(
+                    if mem::offset_of!(ScanOptions, $field) + mem::size_of_val(&result.$field)
+                        <= size
+                    {
+                        result.$field = ptr::addr_of!((*options).$field).read();
+                    }
+                )*
+            };
+        }
+
+        read_fields!(
+            disable_nvidia,
+            disable_vaapi,
+            cuda_ordinals,
+            num_cuda_ordinals,
+            drm_paths,
+            num_drm_paths,
+            codecs,
+            num_codecs,
+            nvidia_library_dirs,
+            num_nvidia_library_dirs,
+            probe_encoder_sessions,
+            nvidia_device_mask,
+            num_nvidia_device_mask,
+            use_primary_context
+        );
+
+        result
+    }
+}
+
+unsafe fn read_strings(strings: &[*const c_char]) -> Vec<String> {
+    strings
+        .iter()
+        .filter(|it| !it.is_null())
+        .map(|&it| CStr::from_ptr(it).to_string_lossy().into_owned())
+        .collect()
+}
+
+unsafe fn read_paths(paths: &[*const c_char]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter(|it| !it.is_null())
+        .map(|&it| PathBuf::from(OsStr::from_bytes(CStr::from_ptr(it).to_bytes())))
+        .collect()
+}
+
+unsafe fn read_slice<'a, T>(ptr: *const T, len: u32) -> Option<&'a [T]> {
+    if ptr.is_null() || len == 0 {
+        None
+    } else {
+        Some(slice::from_raw_parts(ptr, len as usize))
+    }
+}
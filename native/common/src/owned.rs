@@ -0,0 +1,568 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::{
+    ApiVersion, Codec, DecodingSpec, Driver, EncodePreset, EncoderStatus, EncodingSpec, ErrorCode,
+    PciAddress, PixelFormats, PresetConfig,
+};
+
+/// Owned counterpart of [crate::CodecDetails] for use from Rust.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodecDetails {
+    pub codec: Codec,
+    pub decoding_specs: Vec<DecodingSpec>,
+    pub encoding_specs: Vec<EncodingSpec>,
+    /// All capabilities NVENC reports for the codec, `None` if it can't be encoded with NVENC.
+    pub nvenc_caps: Option<NvencCaps>,
+    /// The NVENC presets supported for the codec, `None` if it can't be encoded with NVENC.
+    pub presets: Option<Vec<NvencPreset>>,
+    /// The input buffer formats NVENC accepts for the codec, empty if it can't be encoded with
+    /// NVENC.
+    #[serde(default)]
+    pub input_formats: PixelFormats,
+}
+
+impl CodecDetails {
+    pub fn new(codec: Codec, decoding: Vec<DecodingSpec>, encoding: Vec<EncodingSpec>) -> Self {
+        Self {
+            codec,
+            decoding_specs: decoding,
+            encoding_specs: encoding,
+            nvenc_caps: None,
+            presets: None,
+            input_formats: PixelFormats::default(),
+        }
+    }
+
+    /// Merges the decoding specs of `decoding` and the encoding specs and capabilities of
+    /// `encoding` into a single [CodecDetails] for `codec`.
+    pub fn combine(
+        codec: Codec,
+        decoding: Option<CodecDetails>,
+        encoding: Option<CodecDetails>,
+    ) -> Self {
+        let (encoding_specs, nvenc_caps, presets, input_formats) = encoding
+            .map(|it| {
+                (
+                    it.encoding_specs,
+                    it.nvenc_caps,
+                    it.presets,
+                    it.input_formats,
+                )
+            })
+            .unwrap_or_default();
+
+        Self {
+            codec,
+            decoding_specs: decoding.map(|it| it.decoding_specs).unwrap_or_default(),
+            encoding_specs,
+            nvenc_caps,
+            presets,
+            input_formats,
+        }
+    }
+}
+
+/// Owned counterpart of [crate::NvencPreset] for use from Rust.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NvencPreset {
+    pub preset: EncodePreset,
+    /// The preset GUID reported by the driver.
+    pub guid: Uuid,
+    /// The default configuration of the preset for every tuning info the driver accepts, empty if
+    /// the negotiated NVENC API version is older than 12.2.
+    pub configs: Vec<PresetConfig>,
+}
+
+/// Owned counterpart of [crate::NvencCaps] for use from Rust, see `NV_ENC_CAPS` in
+/// `nvEncodeAPI.h` for details.
+///
+/// A capability is `None` if the driver doesn't support querying it. `raw` contains every value
+/// the driver reported, keyed by its `NV_ENC_CAPS` value, including capabilities this library
+/// doesn't know about yet.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NvencCaps {
+    /// Maximum number of B-frames.
+    pub num_max_bframes: Option<u32>,
+    /// Bitmask of the supported `NV_ENC_PARAMS_RC_MODE` values.
+    pub supported_ratecontrol_modes: Option<u32>,
+    /// `0`: no interlaced encoding, `1`: field mode, `2`: field and frame mode.
+    pub support_field_encoding: Option<u32>,
+    /// Supports monochrome encoding.
+    pub support_monochrome: Option<bool>,
+    /// Supports flexible macroblock ordering.
+    pub support_fmo: Option<bool>,
+    /// Supports quarter-pel motion estimation.
+    pub support_qpelmv: Option<bool>,
+    /// Supports H.264 BDirect modes.
+    pub support_bdirect_mode: Option<bool>,
+    /// Supports H.264 CABAC entropy coding.
+    pub support_cabac: Option<bool>,
+    /// Supports adaptive transform.
+    pub support_adaptive_transform: Option<bool>,
+    /// Supports multi view coding.
+    pub support_stereo_mvc: Option<bool>,
+    /// Maximum number of temporal layers.
+    pub num_max_temporal_layers: Option<u32>,
+    /// Supports hierarchical P-frames.
+    pub support_hierarchical_pframes: Option<bool>,
+    /// Supports hierarchical B-frames.
+    pub support_hierarchical_bframes: Option<bool>,
+    /// Maximum encoding level, see `NV_ENC_LEVEL`.
+    pub level_max: Option<u32>,
+    /// Minimum encoding level, see `NV_ENC_LEVEL`.
+    pub level_min: Option<u32>,
+    /// Supports separate colour plane encoding.
+    pub separate_colour_plane: Option<bool>,
+    /// Maximum output width.
+    pub width_max: Option<u32>,
+    /// Maximum output height.
+    pub height_max: Option<u32>,
+    /// Supports temporal scalability.
+    pub support_temporal_svc: Option<bool>,
+    /// Supports dynamic resolution change without re-initialization.
+    pub support_dyn_res_change: Option<bool>,
+    /// Supports dynamic bitrate change.
+    pub support_dyn_bitrate_change: Option<bool>,
+    /// Supports dynamically forcing constant QP.
+    pub support_dyn_force_constqp: Option<bool>,
+    /// Supports dynamic rate control mode change.
+    pub support_dyn_rcmode_change: Option<bool>,
+    /// Supports sub-frame readback.
+    pub support_subframe_readback: Option<bool>,
+    /// Supports constrained encoding.
+    pub support_constrained_encoding: Option<bool>,
+    /// Supports intra refresh.
+    pub support_intra_refresh: Option<bool>,
+    /// Supports custom VBV buffer size.
+    pub support_custom_vbv_buf_size: Option<bool>,
+    /// Supports dynamic slice mode.
+    pub support_dynamic_slice_mode: Option<bool>,
+    /// Supports reference picture invalidation.
+    pub support_ref_pic_invalidation: Option<bool>,
+    /// Bitmask of the supported preprocessing operations.
+    pub preproc_support: Option<u32>,
+    /// Supports asynchronous encoding.
+    pub async_encode_support: Option<bool>,
+    /// Maximum number of macroblocks per frame.
+    pub mb_num_max: Option<u32>,
+    /// Maximum number of macroblocks per second.
+    pub mb_per_sec_max: Option<u32>,
+    /// Supports YUV 4:4:4 encoding.
+    pub support_yuv444_encode: Option<bool>,
+    /// Supports lossless encoding.
+    pub support_lossless_encode: Option<bool>,
+    /// Supports sample adaptive offset.
+    pub support_sao: Option<bool>,
+    /// Supports motion estimation only mode.
+    pub support_meonly_mode: Option<bool>,
+    /// Supports lookahead encoding.
+    pub support_lookahead: Option<bool>,
+    /// Supports temporal adaptive quantization.
+    pub support_temporal_aq: Option<bool>,
+    /// Supports 10-bit encoding.
+    pub support_10bit_encode: Option<bool>,
+    /// Maximum number of long term reference frames.
+    pub num_max_ltr_frames: Option<u32>,
+    /// Supports weighted prediction.
+    pub support_weighted_prediction: Option<bool>,
+    /// Remaining encoder capacity in percent, if the driver reports it.
+    pub dynamic_query_encoder_capacity: Option<u32>,
+    /// Bitmask of the supported B-frame reference modes.
+    pub support_bframe_ref_mode: Option<u32>,
+    /// Supports emphasis level maps.
+    pub support_emphasis_level_map: Option<bool>,
+    /// Minimum output width.
+    pub width_min: Option<u32>,
+    /// Minimum output height.
+    pub height_min: Option<u32>,
+    /// Supports multiple reference frames.
+    pub support_multiple_ref_frames: Option<bool>,
+    /// Supports alpha layer encoding.
+    pub support_alpha_layer_encoding: Option<bool>,
+    /// Number of encoder engines on the GPU.
+    pub num_encoder_engines: Option<u32>,
+    /// Supports single slice intra refresh.
+    pub single_slice_intra_refresh: Option<bool>,
+    /// Supports disabling encoder state advance.
+    pub disable_enc_state_advance: Option<bool>,
+    /// Supports outputting the reconstructed surface.
+    pub output_recon_surface: Option<bool>,
+    /// Supports outputting block level statistics.
+    pub output_block_stats: Option<bool>,
+    /// Supports outputting row level statistics.
+    pub output_row_stats: Option<bool>,
+    /// Supports temporal filtering.
+    pub support_temporal_filter: Option<bool>,
+    /// Supports configurable lookahead levels.
+    pub support_lookahead_level: Option<bool>,
+    /// Supports unidirectional B-frames.
+    pub support_unidirectional_b: Option<bool>,
+
+    pub raw: BTreeMap<u32, i32>,
+}
+
+/// Owned counterpart of [crate::CudaDeviceInfo] for use from Rust.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CudaDeviceInfo {
+    /// `None` if the driver doesn't report a UUID. `nvidia-smi` prints it with a `GPU-` prefix.
+    pub uuid: Option<Uuid>,
+    pub pci_address: PciAddress,
+    /// Compute capability of the device, e.g. `8.6`.
+    pub compute_capability: ApiVersion,
+    /// Total memory in bytes.
+    pub total_memory: u64,
+    pub multiprocessor_count: u32,
+    pub integrated: bool,
+    pub tcc_driver: bool,
+    /// Version of the CUDA driver API, e.g. `12.4`.
+    pub driver_version: ApiVersion,
+    /// Index of the device in NVML and `nvidia-smi`, `None` if NVML isn't available. Unlike
+    /// [Device::ordinal] it isn't remapped by `CUDA_VISIBLE_DEVICES`.
+    #[serde(default)]
+    pub nvml_index: Option<u32>,
+}
+
+/// Owned counterpart of [crate::Telemetry] for use from Rust, every value is `None` if the driver
+/// doesn't report it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Telemetry {
+    /// GPU utilization in percent.
+    pub gpu_utilization: Option<u32>,
+    /// Encoder utilization in percent.
+    pub encoder_utilization: Option<u32>,
+    /// Decoder utilization in percent.
+    pub decoder_utilization: Option<u32>,
+    /// Number of active encoder sessions.
+    pub encoder_sessions: Option<u32>,
+    /// Average frames per second of all active encoder sessions.
+    pub encoder_average_fps: Option<u32>,
+    /// Average latency of all active encoder sessions in microseconds.
+    pub encoder_average_latency: Option<u32>,
+    /// GPU temperature in degrees Celsius.
+    pub temperature: Option<u32>,
+    /// Power usage in milliwatts.
+    pub power_usage: Option<u32>,
+    /// Total memory in bytes.
+    pub memory_total: Option<u64>,
+    /// Used memory in bytes.
+    pub memory_used: Option<u64>,
+}
+
+/// Owned counterpart of [crate::OpticalFlowCaps] for use from Rust, see `NV_OF_CAPS` in the
+/// Optical Flow SDK for details.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpticalFlowCaps {
+    /// Supported grid sizes of the output flow vectors in pixels, e.g. `[1, 2, 4]`.
+    pub output_grid_sizes: Vec<u32>,
+    /// Supported grid sizes of external hints in pixels, empty if hints aren't supported.
+    pub hint_grid_sizes: Vec<u32>,
+    /// External hints are supported for optical flow.
+    pub hint_with_optical_flow: bool,
+    /// External hints are supported for stereo disparity.
+    pub hint_with_stereo_disparity: bool,
+    pub min_width: u32,
+    pub min_height: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Flow vectors can be restricted to regions of interest.
+    pub roi_supported: bool,
+    /// Maximum number of regions of interest, `0` if they aren't supported.
+    pub max_roi_count: u32,
+}
+
+/// Owned counterpart of [crate::Device] for use from Rust.
+///
+/// NVIDIA devices are identified by their `ordinal`, VA-API devices by their DRM `path`.
+///
+/// In JSON `codecs` is represented as an object keyed by codec.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub driver: Driver,
+    /// The CUDA ordinal, which is remapped by `CUDA_VISIBLE_DEVICES`. [CudaDeviceInfo] identifies
+    /// the physical device.
+    pub ordinal: Option<u8>,
+    pub path: Option<PathBuf>,
+    pub name: Option<String>,
+    #[serde(with = "codecs_by_codec")]
+    pub codecs: Vec<CodecDetails>,
+    /// The NVENC API version negotiated with the driver, the lower of the highest version it
+    /// supports and the one of the bundled `nvEncodeAPI.h`. `None` for devices not using NVENC or
+    /// if the driver doesn't report a supported version.
+    pub nvenc_api_version: Option<ApiVersion>,
+    /// Properties of the CUDA device, `None` for devices not using CUDA.
+    pub cuda: Option<CudaDeviceInfo>,
+    /// Runtime telemetry, `None` if NVML isn't available or the device isn't an NVIDIA device.
+    pub telemetry: Option<Telemetry>,
+    /// The number of NVENC sessions that could be opened concurrently, `None` unless
+    /// [ScanOptions::probe_encoder_sessions] is enabled.
+    pub max_concurrent_sessions: Option<u32>,
+    /// Whether the encoding capabilities could be probed or were taken from a previous scan
+    /// because the device was busy.
+    #[serde(default)]
+    pub encoder_status: EncoderStatus,
+    /// Capabilities of the optical flow accelerator, `None` if the device doesn't have one or
+    /// `libnvidia-opticalflow.so.1` isn't installed.
+    #[serde(default)]
+    pub optical_flow: Option<OpticalFlowCaps>,
+}
+
+impl Device {
+    pub fn new_with_ordinal(
+        driver: Driver,
+        ordinal: u8,
+        name: String,
+        codecs: Vec<CodecDetails>,
+    ) -> Self {
+        Self {
+            driver,
+            ordinal: Some(ordinal),
+            path: None,
+            name: Some(name),
+            codecs,
+            nvenc_api_version: None,
+            cuda: None,
+            telemetry: None,
+            max_concurrent_sessions: None,
+            encoder_status: EncoderStatus::Available,
+            optical_flow: None,
+        }
+    }
+
+    pub fn new_with_path(
+        driver: Driver,
+        path: PathBuf,
+        name: Option<String>,
+        codecs: Vec<CodecDetails>,
+    ) -> Self {
+        Self {
+            driver,
+            ordinal: None,
+            path: Some(path),
+            name,
+            codecs,
+            nvenc_api_version: None,
+            cuda: None,
+            telemetry: None,
+            max_concurrent_sessions: None,
+            encoder_status: EncoderStatus::Available,
+            optical_flow: None,
+        }
+    }
+}
+
+/// Owned counterpart of [crate::Diagnostic] for use from Rust.
+///
+/// `ordinal` and `path` are both `None` if the failure affected the whole driver instead of a
+/// single device.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub driver: Driver,
+    pub ordinal: Option<u8>,
+    pub path: Option<PathBuf>,
+    pub kind: ErrorCode,
+    pub native_code: Option<i64>,
+    pub message: String,
+}
+
+/// Owned counterpart of [crate::LibraryInfo] for use from Rust.
+///
+/// `path` is the file the library was loaded from, `error` why it couldn't be loaded.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryInfo {
+    pub driver: Driver,
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Owned counterpart of [crate::EncDecDevices] for use from Rust.
+///
+/// Contains every device that could be scanned successfully, and a [Diagnostic] for every
+/// device or driver that failed. `libraries` lists the driver libraries every enabled backend
+/// tried to load, so a missing driver can be told apart from a driver without devices.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResult {
+    pub devices: Vec<Device>,
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    pub libraries: Vec<LibraryInfo>,
+}
+
+/// Owned counterpart of [crate::ScanOptions] for use from Rust.
+///
+/// Restricts which drivers, devices and codecs are probed, `None` means no restriction. The
+/// default options scan everything.
+///
+/// `probe_encoder_sessions` determines [Device::max_concurrent_sessions] by opening NVENC sessions
+/// until the driver refuses another one. It's disabled by default because it briefly occupies
+/// every free session of the GPU.
+///
+/// `nvidia_library_dirs` are searched for the NVIDIA libraries before the directories known to
+/// the dynamic linker. The libraries are loaded once per process, so they only take effect if
+/// passed to the first scan.
+///
+/// `use_primary_context` probes NVIDIA devices with their primary context instead of a context
+/// created for the scan. It's cheaper and shares the context with a host application using CUDA
+/// in the same process. Either way the current context of the calling thread is preserved.
+///
+/// `nvidia_device_mask` uses the syntax of `CUDA_VISIBLE_DEVICES`: every entry is either an
+/// NVML index like `nvidia-smi` prints it, a (prefix of a) UUID with an optional `GPU-` prefix, or
+/// a PCI bus ID. Integer entries are matched against [`CudaDeviceInfo::nvml_index`], not the CUDA
+/// ordinal, so unlike `cuda_ordinals` the mask selects the same devices regardless of how
+/// `CUDA_VISIBLE_DEVICES` or `CUDA_DEVICE_ORDER` remap them. Without NVML they match nothing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanOptions {
+    pub nvidia: bool,
+    pub vaapi: bool,
+    pub cuda_ordinals: Option<Vec<u8>>,
+    pub drm_paths: Option<Vec<PathBuf>>,
+    pub codecs: Option<Vec<Codec>>,
+    pub probe_encoder_sessions: bool,
+    pub nvidia_library_dirs: Vec<PathBuf>,
+    pub nvidia_device_mask: Option<Vec<String>>,
+    pub use_primary_context: bool,
+}
+
+impl ScanOptions {
+    pub fn is_driver_enabled(&self, driver: Driver) -> bool {
+        match driver {
+            Driver::Nvidia => self.nvidia,
+            Driver::Vaapi => self.vaapi,
+        }
+    }
+
+    pub fn includes_cuda_ordinal(&self, ordinal: u8) -> bool {
+        self.cuda_ordinals
+            .as_ref()
+            .is_none_or(|it| it.contains(&ordinal))
+    }
+
+    pub fn includes_nvidia_device(&self, cuda: &CudaDeviceInfo) -> bool {
+        self.nvidia_device_mask
+            .as_ref()
+            .is_none_or(|it| it.iter().any(|it| matches_nvidia_device(it, cuda)))
+    }
+
+    pub fn includes_drm_path(&self, path: &Path) -> bool {
+        self.drm_paths
+            .as_ref()
+            .is_none_or(|it| it.iter().any(|it| it == path))
+    }
+
+    pub fn includes_codec(&self, codec: Codec) -> bool {
+        self.codecs.as_ref().is_none_or(|it| it.contains(&codec))
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            nvidia: true,
+            vaapi: true,
+            cuda_ordinals: None,
+            drm_paths: None,
+            codecs: None,
+            probe_encoder_sessions: false,
+            nvidia_library_dirs: Vec::new(),
+            nvidia_device_mask: None,
+            use_primary_context: false,
+        }
+    }
+}
+
+/// Matches an entry of [ScanOptions::nvidia_device_mask] against the identity of a device.
+fn matches_nvidia_device(entry: &str, cuda: &CudaDeviceInfo) -> bool {
+    let entry = entry.trim();
+
+    if let Ok(index) = entry.parse::<u32>() {
+        return cuda.nvml_index == Some(index);
+    }
+
+    if let Ok(pci_address) = entry.parse::<PciAddress>() {
+        return cuda.pci_address == pci_address;
+    }
+
+    let uuid = entry.strip_prefix("GPU-").unwrap_or(entry).to_lowercase();
+
+    !uuid.is_empty()
+        && cuda
+            .uuid
+            .is_some_and(|it| it.hyphenated().to_string().starts_with(&uuid))
+}
+
+mod codecs_by_codec {
+    use serde::de::{MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserializer, Serializer};
+    use std::fmt::Formatter;
+
+    use crate::model::Codec;
+    use crate::owned::CodecDetails;
+
+    pub fn serialize<S: Serializer>(
+        codecs: &[CodecDetails],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(codecs.len()))?;
+        for codec_details in codecs {
+            map.serialize_entry(&codec_details.codec, codec_details)?;
+        }
+
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<CodecDetails>, D::Error> {
+        struct CodecsVisitor;
+
+        impl<'de> Visitor<'de> for CodecsVisitor {
+            type Value = Vec<CodecDetails>;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("an object of codec details keyed by codec")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut result = Vec::with_capacity(map.size_hint().unwrap_or_default());
+                while let Some((_, codec_details)) = map.next_entry::<Codec, CodecDetails>()? {
+                    result.push(codec_details);
+                }
+
+                Ok(result)
+            }
+        }
+
+        deserializer.deserialize_map(CodecsVisitor)
+    }
+}
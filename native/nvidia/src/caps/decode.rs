@@ -17,7 +17,8 @@
 use std::collections::HashMap;
 use std::mem::zeroed;
 
-use common::{Chroma, Codec, CodecDetails, ColorDepth, DecodingSpec};
+use common::owned::CodecDetails;
+use common::{ApiVersion, Chroma, Codec, ColorDepth, DecodingSpec, OutputFormats, ThreeValue};
 use dylib_types::*;
 
 use crate::dylib::{ensure_available, Libs};
@@ -86,11 +87,29 @@ impl CudaDecodeSpec {
             })
             .collect()
     }
+
+    /// Returns all combinations of [CudaDecodeSpec::all] for the specified `codecs`.
+    pub fn for_codecs(codecs: &[Codec]) -> Vec<CudaDecodeSpec> {
+        Self::all()
+            .into_iter()
+            .filter(|spec| codecs.contains(&map_codec_type(spec.codec_type)))
+            .collect()
+    }
 }
 
+/// The first CUDA driver version whose NVDEC reports histogram support (Video Codec SDK 12.1).
+/// Older drivers leave the field zeroed, which doesn't tell whether histograms are supported.
+const HISTOGRAM_DRIVER_VERSION: ApiVersion = ApiVersion::new(12, 1);
+
 /// This function requires an applied [crate::context::CudaContext], so make sure to surround any
 /// call to this function with [crate::context::CudaContext::with_ctx].
-pub fn get_decode_capabilities(specs: &[CudaDecodeSpec]) -> Result<Vec<CodecDetails>, NvidiaError> {
+///
+/// `driver_version` is the CUDA driver version, see
+/// [common::owned::CudaDeviceInfo::driver_version].
+pub fn get_decode_capabilities(
+    specs: &[CudaDecodeSpec],
+    driver_version: ApiVersion,
+) -> Result<Vec<CodecDetails>, NvidiaError> {
     let Libs { lib_cuviddec, .. } = ensure_available()?;
 
     let sym_cuvid_get_decoder_caps = get_sym!(lib_cuviddec, cuvidGetDecoderCaps);
@@ -114,6 +133,18 @@ pub fn get_decode_capabilities(specs: &[CudaDecodeSpec]) -> Result<Vec<CodecDeta
                     color_depth: map_bit_depth(spec.bit_depth),
                     max_width: cuvid_decode_caps.nMaxWidth,
                     max_height: cuvid_decode_caps.nMaxHeight,
+                    min_width: cuvid_decode_caps.nMinWidth.into(),
+                    min_height: cuvid_decode_caps.nMinHeight.into(),
+                    max_mb_count: cuvid_decode_caps.nMaxMBCount,
+                    output_formats: OutputFormats::from_bits(
+                        cuvid_decode_caps.nOutputFormatMask.into(),
+                    ),
+                    histogram_supported: histogram_supported(
+                        cuvid_decode_caps.bIsHistogramSupported,
+                        driver_version,
+                    ),
+                    counter_bit_depth: cuvid_decode_caps.nCounterBitDepth,
+                    max_histogram_bins: cuvid_decode_caps.nMaxHistogramBins,
                 });
         }
     }
@@ -124,6 +155,16 @@ pub fn get_decode_capabilities(specs: &[CudaDecodeSpec]) -> Result<Vec<CodecDeta
         .collect())
 }
 
+fn histogram_supported(is_histogram_supported: u8, driver_version: ApiVersion) -> ThreeValue {
+    if is_histogram_supported != 0 {
+        ThreeValue::True
+    } else if driver_version >= HISTOGRAM_DRIVER_VERSION {
+        ThreeValue::False
+    } else {
+        ThreeValue::Unknown
+    }
+}
+
 fn map_codec_type(cuda_video_codec: cudaVideoCodec) -> Codec {
     #[allow(non_upper_case_globals)]
     match cuda_video_codec {
@@ -163,7 +204,7 @@ fn map_bit_depth(bit_depth: u8) -> ColorDepth {
 #[cfg(test)]
 mod tests {
     use crate::context::CudaContext;
-    use crate::device::enumerate_devices;
+    use crate::device::{enumerate_devices, get_device_info};
     use crate::dylib::is_cuda_loaded;
 
     use super::*;
@@ -178,13 +219,31 @@ mod tests {
         let devices = enumerate_devices()?;
         assert!(!devices.is_empty());
 
+        let driver_version = get_device_info(devices.first().unwrap())?.driver_version;
         let context = CudaContext::new(devices.first().unwrap())?;
 
-        let caps = context.with_ctx(|| get_decode_capabilities(&CudaDecodeSpec::all()))?;
+        let caps =
+            context.with_ctx(|| get_decode_capabilities(&CudaDecodeSpec::all(), driver_version))?;
 
         dbg!(&caps);
         assert!(!caps.is_empty());
 
         Ok(())
     }
+
+    #[test]
+    fn test_histogram_supported() {
+        assert_eq!(
+            histogram_supported(1, ApiVersion::new(12, 4)),
+            ThreeValue::True
+        );
+        assert_eq!(
+            histogram_supported(0, ApiVersion::new(12, 4)),
+            ThreeValue::False
+        );
+        assert_eq!(
+            histogram_supported(0, ApiVersion::new(11, 8)),
+            ThreeValue::Unknown
+        );
+    }
 }
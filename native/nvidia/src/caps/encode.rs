@@ -14,27 +14,39 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use uuid::Uuid;
 
-use common::Chroma::Yuv444;
-use common::{Chroma, Codec, CodecDetails, ColorDepth, EncodeProfile, EncodingSpec};
+use common::owned::{CodecDetails, NvencCaps, NvencPreset};
+use common::{
+    log_debug, log_warn, ApiVersion, Chroma, Codec, ColorDepth, EncodePreset, EncodeProfile,
+    EncodingSpec, MultiPass, PixelFormat, PixelFormats, PresetConfig, RateControlMode, TuningInfo,
+};
 
 use crate::encoder::guid::{
-    AV1_PROFILE_MAIN, CODEC_AV1, CODEC_H264, CODEC_HEVC, H264_PROFILE_BASELINE, H264_PROFILE_HIGH,
-    H264_PROFILE_HIGH_444, H264_PROFILE_MAIN, HEVC_PROFILE_MAIN, HEVC_PROFILE_MAIN10,
+    AV1_PROFILE_MAIN, CODEC_AV1, CODEC_H264, CODEC_HEVC, CODEC_PROFILE_AUTOSELECT,
+    H264_PROFILE_BASELINE, H264_PROFILE_CONSTRAINED_HIGH, H264_PROFILE_HIGH, H264_PROFILE_HIGH_444,
+    H264_PROFILE_MAIN, H264_PROFILE_PROGRESSIVE_HIGH, H264_PROFILE_STEREO, HEVC_PROFILE_FREXT,
+    HEVC_PROFILE_MAIN, HEVC_PROFILE_MAIN10, PRESET_P1, PRESET_P2, PRESET_P3, PRESET_P4, PRESET_P5,
+    PRESET_P6, PRESET_P7,
 };
-use crate::encoder::NvEncoder;
+use crate::encoder::{NvEncoder, MIN_API_VERSION, PRESET_CONFIG_API_VERSION};
+use crate::sys::libnv_encode_api_sys as sys;
 use crate::sys::libnv_encode_api_sys::{
-    _NV_ENC_CAPS_NV_ENC_CAPS_HEIGHT_MAX, _NV_ENC_CAPS_NV_ENC_CAPS_NUM_MAX_BFRAMES,
-    _NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_10BIT_ENCODE, _NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_YUV444_ENCODE,
-    _NV_ENC_CAPS_NV_ENC_CAPS_WIDTH_MAX,
+    _NVENCSTATUS_NV_ENC_ERR_INVALID_PARAM, _NVENCSTATUS_NV_ENC_ERR_INVALID_VERSION,
+    _NVENCSTATUS_NV_ENC_ERR_UNSUPPORTED_PARAM, _NV_ENC_CAPS_NV_ENC_CAPS_EXPOSED_COUNT,
+    NV_ENC_BUFFER_FORMAT, NV_ENC_CONFIG, NV_ENC_TUNING_INFO,
 };
 use crate::NvidiaError;
 
-pub fn get_encode_capabilities(encoder: &NvEncoder) -> Result<Vec<CodecDetails>, NvidiaError> {
-    let mut result: HashMap<Codec, Vec<EncodingSpec>> = HashMap::new();
+/// Queries the encoding capabilities of all codecs supported by `encoder`, or only of `codecs` if
+/// specified.
+pub fn get_encode_capabilities(
+    encoder: &NvEncoder,
+    codecs: Option<&[Codec]>,
+) -> Result<Vec<CodecDetails>, NvidiaError> {
+    let mut result: HashMap<Codec, CodecDetails> = HashMap::new();
 
     let codec_uuids = encoder.get_encode_guids()?;
     for codec_uuid in codec_uuids.iter() {
@@ -43,60 +55,352 @@ pub fn get_encode_capabilities(encoder: &NvEncoder) -> Result<Vec<CodecDetails>,
             None => continue,
         };
 
+        if codecs.is_some_and(|it| !it.contains(&codec)) {
+            continue;
+        }
+
         let profile_uuids = encoder.get_encode_profile_guids(codec_uuid)?;
         let profiles = match_profiles(&profile_uuids);
 
-        let max_width = encoder
-            .get_encode_caps(codec_uuid, _NV_ENC_CAPS_NV_ENC_CAPS_WIDTH_MAX)?
-            .try_into()?;
-        let max_height = encoder
-            .get_encode_caps(codec_uuid, _NV_ENC_CAPS_NV_ENC_CAPS_HEIGHT_MAX)?
-            .try_into()?;
-        let ten_bit_encode_supported = encoder
-            .get_encode_caps(codec_uuid, _NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_10BIT_ENCODE)?
-            == 1;
-        let b_frames_supported =
-            encoder.get_encode_caps(codec_uuid, _NV_ENC_CAPS_NV_ENC_CAPS_NUM_MAX_BFRAMES)? > 0;
-        let yuv_444_encode_supported = encoder
-            .get_encode_caps(codec_uuid, _NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_YUV444_ENCODE)?
-            == 1;
-
-        profiles.into_iter().for_each(|profile| {
-            let base_encoding_spec = EncodingSpec {
-                chroma: Chroma::Yuv420,
-                color_depth: ColorDepth::Bit8,
-                profile,
-                max_width,
-                max_height,
-                b_frames_supported: b_frames_supported.into(),
-            };
-
-            result.entry(codec).or_default().push(base_encoding_spec);
-
-            if ten_bit_encode_supported && !yuv_444_encode_supported {
-                result.entry(codec).or_default().push(EncodingSpec {
-                    color_depth: ColorDepth::Bit10,
-                    ..base_encoding_spec
-                });
-            } else if yuv_444_encode_supported && !ten_bit_encode_supported {
-                result.entry(codec).or_default().push(EncodingSpec {
-                    chroma: Yuv444,
-                    ..base_encoding_spec
-                });
-            } else if ten_bit_encode_supported && yuv_444_encode_supported {
-                result.entry(codec).or_default().push(EncodingSpec {
-                    chroma: Yuv444,
-                    color_depth: ColorDepth::Bit10,
-                    ..base_encoding_spec
-                });
+        let nvenc_caps = get_nvenc_caps(encoder, codec_uuid)?;
+        let presets = match get_presets(encoder, codec_uuid) {
+            Ok(presets) => Some(presets),
+            Err(err) => {
+                log_warn!("Failed to query presets of {:?}: {}", codec, err);
+                None
+            }
+        };
+
+        let input_formats = match encoder.get_input_formats(codec_uuid) {
+            Ok(formats) => formats.iter().filter_map(match_pixel_format).collect(),
+            Err(err) => {
+                log_warn!("Failed to query input formats of {:?}: {}", codec, err);
+                PixelFormats::default()
+            }
+        };
+
+        let max_width = nvenc_caps.width_max.unwrap_or_default();
+        let max_height = nvenc_caps.height_max.unwrap_or_default();
+        let ten_bit_encode_supported = nvenc_caps.support_10bit_encode.unwrap_or_default();
+        let b_frames_supported = nvenc_caps.num_max_bframes.map(|it| it > 0);
+
+        let specs = &mut result
+            .entry(codec)
+            .or_insert_with(|| CodecDetails {
+                nvenc_caps: Some(nvenc_caps),
+                presets,
+                input_formats,
+                ..CodecDetails::new(codec, vec![], vec![])
+            })
+            .encoding_specs;
+
+        profiles.into_iter().for_each(|(profile, profile_uuid)| {
+            let ten_bit = ten_bit_encode_supported && profile_supports_10bit(codec, profile);
+
+            for &chroma in profile_chroma(&profile_uuid) {
+                let encoding_spec = EncodingSpec {
+                    chroma,
+                    color_depth: ColorDepth::Bit8,
+                    profile,
+                    max_width,
+                    max_height,
+                    b_frames_supported: b_frames_supported.into(),
+                    profile_guid: profile_uuid.into_bytes(),
+                };
+
+                specs.push(encoding_spec);
+
+                if ten_bit {
+                    specs.push(EncodingSpec {
+                        color_depth: ColorDepth::Bit10,
+                        ..encoding_spec
+                    });
+                }
+            }
+        });
+    }
+
+    Ok(result.into_values().collect())
+}
+
+/// Queries every capability known to `nvEncodeAPI.h` for `codec_uuid`. Capabilities the driver
+/// rejects or that are newer than the API version of `encoder` are left empty.
+fn get_nvenc_caps(encoder: &NvEncoder, codec_uuid: &Uuid) -> Result<NvencCaps, NvidiaError> {
+    let mut raw = BTreeMap::new();
+
+    for cap in 0.._NV_ENC_CAPS_NV_ENC_CAPS_EXPOSED_COUNT {
+        if encoder.api_version() < cap_api_version(cap) {
+            continue;
+        }
+
+        match encoder.get_encode_caps(codec_uuid, cap) {
+            Ok(value) => {
+                raw.insert(cap, value);
+            }
+            Err(NvidiaError::OperationFailed(status))
+                if status == _NVENCSTATUS_NV_ENC_ERR_INVALID_PARAM
+                    || status == _NVENCSTATUS_NV_ENC_ERR_UNSUPPORTED_PARAM =>
+            {
+                log_debug!(
+                    "Capability {} not supported for codec GUID {}: {}",
+                    cap,
+                    codec_uuid,
+                    status
+                );
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let flag = |cap: u32| raw.get(&cap).map(|&it| it != 0);
+    let number = |cap: u32| raw.get(&cap).and_then(|&it| u32::try_from(it).ok());
+
+    let mut nvenc_caps = NvencCaps {
+        num_max_bframes: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_NUM_MAX_BFRAMES),
+        supported_ratecontrol_modes: number(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORTED_RATECONTROL_MODES,
+        ),
+        support_field_encoding: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_FIELD_ENCODING),
+        support_monochrome: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_MONOCHROME),
+        support_fmo: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_FMO),
+        support_qpelmv: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_QPELMV),
+        support_bdirect_mode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_BDIRECT_MODE),
+        support_cabac: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_CABAC),
+        support_adaptive_transform: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_ADAPTIVE_TRANSFORM),
+        support_stereo_mvc: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_STEREO_MVC),
+        num_max_temporal_layers: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_NUM_MAX_TEMPORAL_LAYERS),
+        support_hierarchical_pframes: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_HIERARCHICAL_PFRAMES,
+        ),
+        support_hierarchical_bframes: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_HIERARCHICAL_BFRAMES,
+        ),
+        level_max: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_LEVEL_MAX),
+        level_min: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_LEVEL_MIN),
+        separate_colour_plane: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SEPARATE_COLOUR_PLANE),
+        width_max: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_WIDTH_MAX),
+        height_max: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_HEIGHT_MAX),
+        support_temporal_svc: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_TEMPORAL_SVC),
+        support_dyn_res_change: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_DYN_RES_CHANGE),
+        support_dyn_bitrate_change: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_DYN_BITRATE_CHANGE),
+        support_dyn_force_constqp: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_DYN_FORCE_CONSTQP),
+        support_dyn_rcmode_change: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_DYN_RCMODE_CHANGE),
+        support_subframe_readback: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_SUBFRAME_READBACK),
+        support_constrained_encoding: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_CONSTRAINED_ENCODING,
+        ),
+        support_intra_refresh: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_INTRA_REFRESH),
+        support_custom_vbv_buf_size: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_CUSTOM_VBV_BUF_SIZE,
+        ),
+        support_dynamic_slice_mode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_DYNAMIC_SLICE_MODE),
+        support_ref_pic_invalidation: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_REF_PIC_INVALIDATION,
+        ),
+        preproc_support: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_PREPROC_SUPPORT),
+        async_encode_support: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_ASYNC_ENCODE_SUPPORT),
+        mb_num_max: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_MB_NUM_MAX),
+        mb_per_sec_max: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_MB_PER_SEC_MAX),
+        support_yuv444_encode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_YUV444_ENCODE),
+        support_lossless_encode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_LOSSLESS_ENCODE),
+        support_sao: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_SAO),
+        support_meonly_mode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_MEONLY_MODE),
+        support_lookahead: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_LOOKAHEAD),
+        support_temporal_aq: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_TEMPORAL_AQ),
+        support_10bit_encode: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_10BIT_ENCODE),
+        num_max_ltr_frames: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_NUM_MAX_LTR_FRAMES),
+        support_weighted_prediction: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_WEIGHTED_PREDICTION,
+        ),
+        dynamic_query_encoder_capacity: number(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_DYNAMIC_QUERY_ENCODER_CAPACITY,
+        ),
+        support_bframe_ref_mode: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_BFRAME_REF_MODE),
+        support_emphasis_level_map: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_EMPHASIS_LEVEL_MAP),
+        width_min: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_WIDTH_MIN),
+        height_min: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_HEIGHT_MIN),
+        support_multiple_ref_frames: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_MULTIPLE_REF_FRAMES,
+        ),
+        support_alpha_layer_encoding: flag(
+            sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_ALPHA_LAYER_ENCODING,
+        ),
+        num_encoder_engines: number(sys::_NV_ENC_CAPS_NV_ENC_CAPS_NUM_ENCODER_ENGINES),
+        single_slice_intra_refresh: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SINGLE_SLICE_INTRA_REFRESH),
+        disable_enc_state_advance: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_DISABLE_ENC_STATE_ADVANCE),
+        output_recon_surface: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_OUTPUT_RECON_SURFACE),
+        output_block_stats: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_OUTPUT_BLOCK_STATS),
+        output_row_stats: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_OUTPUT_ROW_STATS),
+        support_temporal_filter: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_TEMPORAL_FILTER),
+        support_lookahead_level: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_LOOKAHEAD_LEVEL),
+        support_unidirectional_b: flag(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_UNIDIRECTIONAL_B),
+        ..NvencCaps::default()
+    };
+
+    nvenc_caps.raw = raw;
+
+    Ok(nvenc_caps)
+}
+
+/// Enumerates the presets `encoder` supports for `codec_uuid` along with their default
+/// configuration for every tuning info. Tuning infos the driver rejects, e.g. `UltraHighQuality` on
+/// older GPUs, are left out. The configurations are only queried if `encoder` was opened with
+/// [PRESET_CONFIG_API_VERSION], otherwise they're empty.
+fn get_presets(encoder: &NvEncoder, codec_uuid: &Uuid) -> Result<Vec<NvencPreset>, NvidiaError> {
+    const TUNING_INFOS: [TuningInfo; 5] = [
+        TuningInfo::HighQuality,
+        TuningInfo::LowLatency,
+        TuningInfo::UltraLowLatency,
+        TuningInfo::Lossless,
+        TuningInfo::UltraHighQuality,
+    ];
+
+    let tuning_infos: &[TuningInfo] = if encoder.api_version() >= PRESET_CONFIG_API_VERSION {
+        &TUNING_INFOS
+    } else {
+        log_debug!(
+            "Skipping preset configurations, NVENC API {} is older than {}",
+            encoder.api_version(),
+            PRESET_CONFIG_API_VERSION
+        );
+        &[]
+    };
+
+    let mut result = Vec::new();
+
+    for preset_uuid in encoder.get_encode_preset_guids(codec_uuid)? {
+        let mut configs = Vec::new();
+
+        for &tuning_info in tuning_infos {
+            match encoder.get_encode_preset_config(
+                codec_uuid,
+                &preset_uuid,
+                nv_tuning_info(tuning_info),
+            ) {
+                Ok(preset_config) => {
+                    configs.extend(map_preset_config(tuning_info, &preset_config.presetCfg))
+                }
+                Err(NvidiaError::OperationFailed(status))
+                    if status == _NVENCSTATUS_NV_ENC_ERR_INVALID_PARAM
+                        || status == _NVENCSTATUS_NV_ENC_ERR_UNSUPPORTED_PARAM
+                        || status == _NVENCSTATUS_NV_ENC_ERR_INVALID_VERSION =>
+                {
+                    log_debug!(
+                        "Preset {} not supported with {:?} for codec GUID {}: {}",
+                        preset_uuid,
+                        tuning_info,
+                        codec_uuid,
+                        status
+                    );
+                }
+                Err(err) => return Err(err),
             }
+        }
+
+        result.push(NvencPreset {
+            preset: match_preset(&preset_uuid),
+            guid: preset_uuid,
+            configs,
         });
     }
 
-    Ok(result
-        .into_iter()
-        .map(|(codec, specs)| CodecDetails::new(codec, vec![], specs))
-        .collect())
+    Ok(result)
+}
+
+/// Returns the API version `cap` was added in. Only the capabilities added by 12.x are tracked, the
+/// driver rejects older ones it doesn't know anyway.
+fn cap_api_version(cap: u32) -> ApiVersion {
+    match cap {
+        sys::_NV_ENC_CAPS_NV_ENC_CAPS_DISABLE_ENC_STATE_ADVANCE
+            ..=sys::_NV_ENC_CAPS_NV_ENC_CAPS_OUTPUT_ROW_STATS => ApiVersion::new(12, 1),
+        sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_TEMPORAL_FILTER
+            ..=sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_UNIDIRECTIONAL_B => ApiVersion::new(12, 2),
+        _ => MIN_API_VERSION,
+    }
+}
+
+fn nv_tuning_info(tuning_info: TuningInfo) -> NV_ENC_TUNING_INFO {
+    match tuning_info {
+        TuningInfo::HighQuality => sys::NV_ENC_TUNING_INFO_NV_ENC_TUNING_INFO_HIGH_QUALITY,
+        TuningInfo::LowLatency => sys::NV_ENC_TUNING_INFO_NV_ENC_TUNING_INFO_LOW_LATENCY,
+        TuningInfo::UltraLowLatency => sys::NV_ENC_TUNING_INFO_NV_ENC_TUNING_INFO_ULTRA_LOW_LATENCY,
+        TuningInfo::Lossless => sys::NV_ENC_TUNING_INFO_NV_ENC_TUNING_INFO_LOSSLESS,
+        TuningInfo::UltraHighQuality => {
+            sys::NV_ENC_TUNING_INFO_NV_ENC_TUNING_INFO_ULTRA_HIGH_QUALITY
+        }
+    }
+}
+
+fn map_preset_config(tuning_info: TuningInfo, config: &NV_ENC_CONFIG) -> Option<PresetConfig> {
+    let rc_params = &config.rcParams;
+
+    let rate_control_mode = match rc_params.rateControlMode {
+        sys::_NV_ENC_PARAMS_RC_MODE_NV_ENC_PARAMS_RC_CONSTQP => RateControlMode::ConstQp,
+        sys::_NV_ENC_PARAMS_RC_MODE_NV_ENC_PARAMS_RC_VBR => RateControlMode::Vbr,
+        sys::_NV_ENC_PARAMS_RC_MODE_NV_ENC_PARAMS_RC_CBR => RateControlMode::Cbr,
+        other => {
+            log_debug!("Unknown rate control mode: {}", other);
+            return None;
+        }
+    };
+
+    let multi_pass = match rc_params.multiPass {
+        sys::_NV_ENC_MULTI_PASS_NV_ENC_MULTI_PASS_DISABLED => MultiPass::SinglePass,
+        sys::_NV_ENC_MULTI_PASS_NV_ENC_TWO_PASS_QUARTER_RESOLUTION => {
+            MultiPass::TwoPassQuarterResolution
+        }
+        sys::_NV_ENC_MULTI_PASS_NV_ENC_TWO_PASS_FULL_RESOLUTION => MultiPass::TwoPassFullResolution,
+        other => {
+            log_debug!("Unknown multi pass mode: {}", other);
+            return None;
+        }
+    };
+
+    Some(PresetConfig {
+        tuning_info,
+        gop_length: config.gopLength,
+        frame_interval_p: config.frameIntervalP,
+        rate_control_mode,
+        multi_pass,
+        lookahead: rc_params.enableLookahead() != 0,
+        lookahead_depth: rc_params.lookaheadDepth,
+        adaptive_quantization: rc_params.enableAQ() != 0,
+        temporal_adaptive_quantization: rc_params.enableTemporalAQ() != 0,
+        average_bitrate: rc_params.averageBitRate,
+        max_bitrate: rc_params.maxBitRate,
+    })
+}
+
+fn match_preset(uuid: &Uuid) -> EncodePreset {
+    match *uuid {
+        PRESET_P1 => EncodePreset::P1,
+        PRESET_P2 => EncodePreset::P2,
+        PRESET_P3 => EncodePreset::P3,
+        PRESET_P4 => EncodePreset::P4,
+        PRESET_P5 => EncodePreset::P5,
+        PRESET_P6 => EncodePreset::P6,
+        PRESET_P7 => EncodePreset::P7,
+        _ => EncodePreset::Legacy,
+    }
+}
+
+fn match_pixel_format(format: &NV_ENC_BUFFER_FORMAT) -> Option<PixelFormat> {
+    match *format {
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_NV12 => Some(PixelFormat::Nv12),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_YV12 => Some(PixelFormat::Yv12),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_IYUV => Some(PixelFormat::Iyuv),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_YUV444 => Some(PixelFormat::Yuv444),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_YUV420_10BIT => Some(PixelFormat::P010),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_YUV444_10BIT => {
+            Some(PixelFormat::Yuv444Bit10)
+        }
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_ARGB => Some(PixelFormat::Argb),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_ARGB10 => Some(PixelFormat::Argb10),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_AYUV => Some(PixelFormat::Ayuv),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_ABGR => Some(PixelFormat::Abgr),
+        sys::_NV_ENC_BUFFER_FORMAT_NV_ENC_BUFFER_FORMAT_ABGR10 => Some(PixelFormat::Abgr10),
+        _ => None,
+    }
 }
 
 fn match_codec(uuid: &Uuid) -> Option<Codec> {
@@ -107,50 +411,82 @@ fn match_codec(uuid: &Uuid) -> Option<Codec> {
     } else if uuid == &CODEC_AV1 {
         Some(Codec::Av1)
     } else {
-        eprintln!("Unknown codec GUID: {}", uuid);
+        log_warn!("Unknown codec GUID: {}", uuid);
         None
     }
 }
 
-fn match_profiles(uuids: &[Uuid]) -> Vec<EncodeProfile> {
-    static IGNORED_PROFILES: [u32; 3] = [0x40847bf5, 0xbfd6f8e7, 0x51ec32b5];
-
+/// Maps the profile GUIDs reported by NVENC to [EncodeProfile]s, GUIDs unknown to the bundled
+/// `nvEncodeAPI.h` are mapped to [EncodeProfile::Other] and kept along with their GUID.
+fn match_profiles(uuids: &[Uuid]) -> Vec<(EncodeProfile, Uuid)> {
     uuids
         .iter()
-        .filter_map(|uuid| {
-            if uuid == &H264_PROFILE_BASELINE {
-                Some(EncodeProfile::Baseline)
-            } else if uuid == &H264_PROFILE_MAIN {
-                Some(EncodeProfile::Main)
-            } else if uuid == &H264_PROFILE_HIGH {
-                Some(EncodeProfile::High)
-            } else if uuid == &H264_PROFILE_HIGH_444 {
-                Some(EncodeProfile::High444)
-            } else if uuid == &HEVC_PROFILE_MAIN {
-                Some(EncodeProfile::Main)
-            } else if uuid == &HEVC_PROFILE_MAIN10 {
-                Some(EncodeProfile::Main10)
-            } else if uuid == &AV1_PROFILE_MAIN {
-                Some(EncodeProfile::Main)
-            } else {
-                let (first_field, ..) = uuid.as_fields();
-                if !IGNORED_PROFILES.contains(&first_field) {
-                    eprintln!("Unknown profile GUID: {}", uuid);
-                }
-
-                None
-            }
-        })
+        .map(|&uuid| (match_profile(&uuid), uuid))
         .collect()
 }
 
+fn match_profile(uuid: &Uuid) -> EncodeProfile {
+    if uuid == &CODEC_PROFILE_AUTOSELECT {
+        EncodeProfile::AutoSelect
+    } else if uuid == &H264_PROFILE_BASELINE {
+        EncodeProfile::Baseline
+    } else if uuid == &H264_PROFILE_MAIN {
+        EncodeProfile::Main
+    } else if uuid == &H264_PROFILE_HIGH {
+        EncodeProfile::High
+    } else if uuid == &H264_PROFILE_HIGH_444 {
+        EncodeProfile::High444
+    } else if uuid == &H264_PROFILE_STEREO {
+        EncodeProfile::Stereo
+    } else if uuid == &H264_PROFILE_PROGRESSIVE_HIGH {
+        EncodeProfile::ProgressiveHigh
+    } else if uuid == &H264_PROFILE_CONSTRAINED_HIGH {
+        EncodeProfile::ConstrainedHigh
+    } else if uuid == &HEVC_PROFILE_MAIN {
+        EncodeProfile::Main
+    } else if uuid == &HEVC_PROFILE_MAIN10 {
+        EncodeProfile::Main10
+    } else if uuid == &HEVC_PROFILE_FREXT {
+        EncodeProfile::Frext
+    } else if uuid == &AV1_PROFILE_MAIN {
+        EncodeProfile::Main
+    } else {
+        log_debug!("Unknown profile GUID: {}", uuid);
+        EncodeProfile::Other
+    }
+}
+
+/// Returns the chroma subsamplings the profile `uuid` encodes. NVENC only lists the profiles the
+/// device can encode, so 4:4:4 support is derived from them instead of from
+/// `NV_ENC_CAPS_SUPPORT_YUV444_ENCODE`, which is only reported per codec.
+fn profile_chroma(uuid: &Uuid) -> &'static [Chroma] {
+    if uuid == &H264_PROFILE_HIGH_444 || uuid == &HEVC_PROFILE_FREXT {
+        &[Chroma::Yuv420, Chroma::Yuv444]
+    } else {
+        &[Chroma::Yuv420]
+    }
+}
+
+/// Returns whether `profile` of `codec` allows 10-bit encoding, which additionally requires
+/// `NV_ENC_CAPS_SUPPORT_10BIT_ENCODE`.
+fn profile_supports_10bit(codec: Codec, profile: EncodeProfile) -> bool {
+    matches!(
+        (codec, profile),
+        (Codec::Hevc, EncodeProfile::Main10 | EncodeProfile::Frext)
+            | (Codec::Av1, EncodeProfile::Main)
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::caps::encode::get_encode_capabilities;
+    use common::{ApiVersion, Chroma, PixelFormat};
+
+    use crate::caps::encode::{cap_api_version, get_encode_capabilities, profile_chroma};
     use crate::context::CudaContext;
     use crate::device::enumerate_devices;
     use crate::dylib::is_cuda_loaded;
-    use crate::encoder::NvEncoder;
+    use crate::encoder::{guid, NvEncoder, MIN_API_VERSION};
+    use crate::sys::libnv_encode_api_sys as sys;
     use crate::*;
 
     #[test]
@@ -168,14 +504,56 @@ mod tests {
         context.with_floating_ctx(|context| {
             let encoder = NvEncoder::new(context)?;
 
-            let encode_capabilities = get_encode_capabilities(&encoder)?;
+            let encode_capabilities = get_encode_capabilities(&encoder, None)?;
 
             dbg!(&encode_capabilities);
             assert!(!encode_capabilities.is_empty());
+            assert!(encode_capabilities
+                .iter()
+                .all(|it| it.presets.as_ref().is_some_and(|it| !it.is_empty())));
+            assert!(encode_capabilities
+                .iter()
+                .all(|it| it.input_formats.contains(PixelFormat::Nv12)));
+            assert!(encode_capabilities
+                .iter()
+                .flat_map(|it| it.encoding_specs.iter())
+                .all(|it| it.profile_guid != [0; 16]));
 
             Ok(())
         })?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_profile_chroma() {
+        assert_eq!(
+            profile_chroma(&guid::HEVC_PROFILE_FREXT),
+            &[Chroma::Yuv420, Chroma::Yuv444]
+        );
+        assert_eq!(
+            profile_chroma(&guid::H264_PROFILE_HIGH_444),
+            &[Chroma::Yuv420, Chroma::Yuv444]
+        );
+        assert_eq!(
+            profile_chroma(&guid::HEVC_PROFILE_MAIN10),
+            &[Chroma::Yuv420]
+        );
+    }
+
+    #[test]
+    fn test_cap_api_version() {
+        assert_eq!(
+            cap_api_version(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_10BIT_ENCODE),
+            MIN_API_VERSION
+        );
+        assert_eq!(
+            cap_api_version(sys::_NV_ENC_CAPS_NV_ENC_CAPS_OUTPUT_RECON_SURFACE),
+            ApiVersion::new(12, 1)
+        );
+        assert_eq!(
+            cap_api_version(sys::_NV_ENC_CAPS_NV_ENC_CAPS_SUPPORT_LOOKAHEAD_LEVEL),
+            ApiVersion::new(12, 2)
+        );
+    }
 }
@@ -18,13 +18,16 @@ use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use std::mem::zeroed;
 use std::panic::{catch_unwind, panic_any, UnwindSafe};
+use std::ptr;
 use std::sync::Mutex;
 
 use dylib_types::*;
 
 use crate::device::CudaDevice;
 use crate::dylib::{ensure_available, Libs};
-use crate::sys::libcuviddec_sys::CUcontext;
+use crate::sys::libcuviddec_sys::{
+    cudaError_enum_CUDA_ERROR_DEVICE_UNAVAILABLE, cudaError_enum_CUDA_SUCCESS, CUcontext, CUdevice,
+};
 use crate::NvidiaError;
 
 #[allow(non_camel_case_types, dead_code)]
@@ -37,31 +40,76 @@ mod dylib_types {
     pub type cuCtxDestroy = unsafe extern "C" fn(CUcontext) -> CUresult;
     pub type cuCtxPushCurrent = unsafe extern "C" fn(CUcontext) -> CUresult;
     pub type cuCtxPopCurrent = unsafe extern "C" fn(*mut CUcontext) -> CUresult;
+    pub type cuCtxGetCurrent = unsafe extern "C" fn(*mut CUcontext) -> CUresult;
+    pub type cuCtxSetCurrent = unsafe extern "C" fn(CUcontext) -> CUresult;
+    pub type cuDevicePrimaryCtxRetain = unsafe extern "C" fn(*mut CUcontext, CUdevice) -> CUresult;
+    pub type cuDevicePrimaryCtxRelease = unsafe extern "C" fn(CUdevice) -> CUresult;
 }
 
+/// Returns whether `error` was caused by the device refusing another context, e.g. because it runs
+/// in exclusive-process compute mode and another process already holds a context.
+pub fn is_device_unavailable(error: &NvidiaError) -> bool {
+    matches!(
+        error,
+        NvidiaError::OperationFailed(status)
+            if *status == cudaError_enum_CUDA_ERROR_DEVICE_UNAVAILABLE
+    )
+}
+
+/// Returns the context that is current on the calling thread, null if there is none.
+pub fn current_context() -> Result<CUcontext, NvidiaError> {
+    let Libs { lib_cuda, .. } = ensure_available()?;
+
+    let sym_cu_ctx_get_current = get_sym!(lib_cuda, cuCtxGetCurrent);
+
+    let mut cu_context: CUcontext = ptr::null_mut();
+    call_cuda_sym!(sym_cu_ctx_get_current(&mut cu_context));
+
+    Ok(cu_context)
+}
+
+/// Makes `saved` the current context of the calling thread again, unless it still is.
+fn restore_current(saved: CUcontext) -> Result<(), NvidiaError> {
+    if current_context()? == saved {
+        return Ok(());
+    }
+
+    let Libs { lib_cuda, .. } = ensure_available()?;
+
+    let sym_cu_ctx_set_current = get_sym!(lib_cuda, cuCtxSetCurrent);
+    call_cuda_sym!(sym_cu_ctx_set_current(saved));
+
+    Ok(())
+}
+
+/// A CUDA context used to probe a device, either created for the scan or the primary context of
+/// the device retained for the duration of the scan.
+///
+/// Neither the creation nor the use of the context changes the current context of the calling
+/// thread, so a host application running CUDA work on the same thread is left alone.
 #[derive(Debug)]
 pub struct CudaContext<'ctx> {
     context: Mutex<UnsafeCell<CUcontext>>,
+    /// The device whose primary context was retained, `None` if the context was created.
+    primary_device: Option<CUdevice>,
     phantom: PhantomData<&'ctx CudaContext<'ctx>>,
 }
 
 impl<'a> Drop for CudaContext<'a> {
     fn drop(&mut self) {
-        let Libs { lib_cuda, .. } =
-            ensure_available().expect("How did we get here if lib_cuda isn't available?");
-
-        let sym_cu_ctx_destroy =
-            unsafe { lib_cuda.get::<cuCtxDestroy>(stringify!(cuCtxDestroy).as_bytes()) }
-                .expect("cuCtxDestroy not found in lib_cuda");
-
-        let context = self.context.lock().unwrap();
-        unsafe {
-            sym_cu_ctx_destroy(*context.get());
+        let context = unsafe { *self.context.get_mut().unwrap().get() };
+        // already released if a closure panicked
+        if context.is_null() {
+            return;
         }
+
+        self.release(context)
+            .expect("How did we get here if lib_cuda isn't available?");
     }
 }
 
 impl<'a> CudaContext<'a> {
+    /// Creates a new context on `device`, which is destroyed again when dropped.
     pub fn new(device: &CudaDevice) -> Result<Self, NvidiaError> {
         let Libs { lib_cuda, .. } = ensure_available()?;
 
@@ -70,19 +118,45 @@ impl<'a> CudaContext<'a> {
 
         let mut cu_context: CUcontext = unsafe { zeroed() };
 
+        // cuCtxCreate pushes the new context onto the stack of the calling thread, popping it
+        // makes the previous context current again
         call_cuda_sym!(sym_cu_ctx_create(&mut cu_context, 0, device.handle));
         call_cuda_sym!(sym_cu_ctx_pop_current(&mut cu_context));
 
         Ok(Self {
             context: Mutex::new(UnsafeCell::new(cu_context)),
+            primary_device: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Retains the primary context of `device`, which is shared with every other user of the
+    /// device in this process and released again when dropped.
+    ///
+    /// This avoids the cost and the device memory of a separate context, and doesn't disturb a
+    /// host application that already uses the primary context.
+    pub fn retain_primary(device: &CudaDevice) -> Result<Self, NvidiaError> {
+        let Libs { lib_cuda, .. } = ensure_available()?;
+
+        let sym_cu_device_primary_ctx_retain = get_sym!(lib_cuda, cuDevicePrimaryCtxRetain);
+
+        let mut cu_context: CUcontext = unsafe { zeroed() };
+        call_cuda_sym!(sym_cu_device_primary_ctx_retain(
+            &mut cu_context,
+            device.handle
+        ));
+
+        Ok(Self {
+            context: Mutex::new(UnsafeCell::new(cu_context)),
+            primary_device: Some(device.handle),
             phantom: PhantomData,
         })
     }
 
     /// Executes [f] while making sure the CUDA context is correctly applied to the GPU and cleaned
-    /// up afterward.
+    /// up afterward. The context that was current before is current again when this returns.
     ///
-    /// If `f` panics, the CUDA context is destroyed to release any resources and the panic
+    /// If `f` panics, the CUDA context is released to free any resources and the panic
     /// propagated.
     pub fn with_ctx<F, T>(&self, f: F) -> Result<T, NvidiaError>
     where
@@ -95,31 +169,42 @@ impl<'a> CudaContext<'a> {
         let sym_cu_ctx_push_current = get_sym!(lib_cuda, cuCtxPushCurrent);
         let sym_cu_ctx_pop_current = get_sym!(lib_cuda, cuCtxPopCurrent);
 
+        let saved = current_context()?;
         call_cuda_sym!(sym_cu_ctx_push_current(*context.get()));
 
-        let f_result = match catch_unwind(f) {
+        let f_result = catch_unwind(f);
+
+        // This won't change the actual context pointer, we just need
+        // something to pass to the pop call
+        let mut popped: CUcontext = ptr::null_mut();
+        let pop_status = unsafe { sym_cu_ctx_pop_current(&mut popped) };
+        // f may have left contexts of its own on the stack
+        let restored = restore_current(saved);
+
+        let f_result = match f_result {
             Ok(res) => res,
             Err(err) => {
                 // we clean up the context on panic, so we don't leave the GPU in an
                 // unexpected state before propagating the original panic value
-                self.destroy(unsafe { *context.get() })?;
+                self.discard(unsafe { &mut *context.get() })?;
 
                 panic_any(err);
             }
         };
 
-        let ctx_ptr = context.get();
-        // This won't change the actual context pointer, we just need
-        // something to pass to the pop call
-        call_cuda_sym!(sym_cu_ctx_pop_current(ctx_ptr));
+        if pop_status != cudaError_enum_CUDA_SUCCESS {
+            return Err(NvidiaError::OperationFailed(pop_status));
+        }
+
+        restored?;
 
         f_result
     }
 
     /// Executes [f] and supplies the CUDA context as a floating context instead of pushing it to
-    /// the GPU directly.
+    /// the GPU directly. The context that was current before is current again when this returns.
     ///
-    /// If `f` panics, the CUDA context is destroyed to release any resources and the panic
+    /// If `f` panics, the CUDA context is released to free any resources and the panic
     /// propagated.
     pub fn with_floating_ctx<F, T>(&self, f: F) -> Result<T, NvidiaError>
     where
@@ -127,21 +212,45 @@ impl<'a> CudaContext<'a> {
     {
         let context = self.context.lock().unwrap();
 
-        match catch_unwind(|| f(unsafe { &mut *context.get() })) {
-            Ok(res) => res,
+        let saved = current_context()?;
+        let f_result = catch_unwind(|| f(unsafe { &mut *context.get() }));
+        let restored = restore_current(saved);
+
+        match f_result {
+            Ok(res) => restored.and(res),
             Err(err) => {
-                self.destroy(unsafe { *context.get() })?;
+                self.discard(unsafe { &mut *context.get() })?;
                 panic_any(err);
             }
         }
     }
 
-    fn destroy(&self, ctx: CUcontext) -> Result<(), NvidiaError> {
+    /// Releases `ctx` early and clears it, so it isn't released a second time when dropped.
+    fn discard(&self, ctx: &mut CUcontext) -> Result<(), NvidiaError> {
+        self.release(*ctx)?;
+        *ctx = ptr::null_mut();
+
+        Ok(())
+    }
+
+    /// Destroys `ctx` if it was created, or gives up the reference to the primary context.
+    fn release(&self, ctx: CUcontext) -> Result<(), NvidiaError> {
         let Libs { lib_cuda, .. } = ensure_available()?;
 
-        let sym_cu_ctx_destroy = get_sym!(lib_cuda, cuCtxDestroy);
-        unsafe {
-            sym_cu_ctx_destroy(ctx);
+        match self.primary_device {
+            Some(device) => {
+                let sym_cu_device_primary_ctx_release =
+                    get_sym!(lib_cuda, cuDevicePrimaryCtxRelease);
+                unsafe {
+                    sym_cu_device_primary_ctx_release(device);
+                }
+            }
+            None => {
+                let sym_cu_ctx_destroy = get_sym!(lib_cuda, cuCtxDestroy);
+                unsafe {
+                    sym_cu_ctx_destroy(ctx);
+                }
+            }
         }
 
         Ok(())
@@ -181,4 +290,38 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_primary_context_keeps_current_context() -> Result<(), Box<dyn Error>> {
+        if !is_cuda_loaded() {
+            eprintln!("libcuda.so not available");
+            return Ok(());
+        }
+
+        let devices = enumerate_devices()?;
+        assert!(!devices.is_empty());
+
+        // stands in for the context of a host application using the same thread
+        let host_context = CudaContext::new(devices.first().unwrap())?;
+
+        host_context.with_ctx(|| {
+            let current = current_context()?;
+
+            let context = CudaContext::retain_primary(devices.first().unwrap())?;
+            assert_eq!(current_context()?, current);
+
+            context.with_ctx(|| Ok(()))?;
+            assert_eq!(current_context()?, current);
+
+            context.with_floating_ctx(|_| Ok(()))?;
+            assert_eq!(current_context()?, current);
+
+            drop(context);
+            assert_eq!(current_context()?, current);
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
 }
@@ -19,11 +19,21 @@ use std::mem::zeroed;
 
 use uuid::Uuid;
 
+use common::owned::CudaDeviceInfo;
+use common::{log_debug, ApiVersion};
+
 use dylib_types::*;
 
 use crate::dylib::{ensure_available, Libs};
+use crate::nvml::nvml;
 pub use crate::sys::libcuviddec_sys::CUdevice;
-use crate::sys::libcuviddec_sys::CUuuid;
+use crate::sys::libcuviddec_sys::{
+    CUdevice_attribute, CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR,
+    CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR,
+    CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_INTEGRATED,
+    CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT,
+    CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_TCC_DRIVER, CUuuid,
+};
 use crate::NvidiaError;
 use crate::{call_cuda_sym, get_sym};
 
@@ -31,18 +41,27 @@ use crate::{call_cuda_sym, get_sym};
 mod dylib_types {
     use std::ffi::{c_char, c_int, c_uint};
 
-    use crate::sys::libcuviddec_sys::{CUdevice, CUresult, CUuuid};
+    use crate::sys::libcuviddec_sys::{CUdevice, CUdevice_attribute, CUresult, CUuuid};
 
     pub type cuDeviceGet = unsafe extern "C" fn(*mut CUdevice, c_uint) -> CUresult;
     pub type cuDeviceGetCount = unsafe extern "C" fn(*mut c_uint) -> CUresult;
     pub type cuDeviceGetName =
         unsafe extern "C" fn(*mut c_char, c_int: c_int, CUdevice) -> CUresult;
     pub type cuDeviceGetUuid = unsafe extern "C" fn(*mut CUuuid, CUdevice) -> CUresult;
+    pub type cuDeviceGetPCIBusId =
+        unsafe extern "C" fn(*mut c_char, c_int: c_int, CUdevice) -> CUresult;
+    pub type cuDeviceGetAttribute =
+        unsafe extern "C" fn(*mut c_int, CUdevice_attribute, CUdevice) -> CUresult;
+    pub type cuDeviceTotalMem_v2 = unsafe extern "C" fn(*mut usize, CUdevice) -> CUresult;
+    pub type cuDriverGetVersion = unsafe extern "C" fn(*mut c_int) -> CUresult;
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CudaDevice {
     pub handle: CUdevice,
+    /// Position of the device among the devices visible to CUDA, which is remapped by
+    /// `CUDA_VISIBLE_DEVICES`.
+    pub ordinal: u32,
     pub name: String,
     pub uuid: Uuid,
 }
@@ -90,6 +109,7 @@ pub fn enumerate_devices() -> Result<Vec<CudaDevice>, NvidiaError> {
 
         devices.push(CudaDevice {
             handle: cu_device,
+            ordinal,
             name: cu_name_raw.to_string_lossy().to_string(),
             uuid,
         })
@@ -98,6 +118,83 @@ pub fn enumerate_devices() -> Result<Vec<CudaDevice>, NvidiaError> {
     Ok(devices)
 }
 
+/// Queries the PCI location, compute capability, memory and other properties of `device`. The
+/// NVML index is only known if NVML is available.
+pub fn get_device_info(device: &CudaDevice) -> Result<CudaDeviceInfo, NvidiaError> {
+    let Libs { lib_cuda, .. } = ensure_available()?;
+
+    let sym_cu_device_get_pci_bus_id = get_sym!(lib_cuda, cuDeviceGetPCIBusId);
+    let sym_cu_device_get_attribute = get_sym!(lib_cuda, cuDeviceGetAttribute);
+    let sym_cu_device_total_mem = get_sym!(lib_cuda, cuDeviceTotalMem_v2);
+    let sym_cu_driver_get_version = get_sym!(lib_cuda, cuDriverGetVersion);
+
+    let pci_bus_id_buffer = [0u8; 32];
+    call_cuda_sym!(sym_cu_device_get_pci_bus_id(
+        pci_bus_id_buffer.as_ptr() as *mut c_char,
+        pci_bus_id_buffer.len().try_into()?,
+        device.handle
+    ));
+
+    let pci_bus_id = CStr::from_bytes_until_nul(&pci_bus_id_buffer)
+        .unwrap()
+        .to_string_lossy();
+    let pci_address = pci_bus_id
+        .parse()
+        .map_err(|_| NvidiaError::FailedToParsePciBusId(pci_bus_id.to_string()))?;
+
+    let get_attribute = |attribute: CUdevice_attribute| -> Result<i32, NvidiaError> {
+        let mut value = 0;
+        call_cuda_sym!(sym_cu_device_get_attribute(
+            &mut value,
+            attribute,
+            device.handle
+        ));
+
+        Ok(value)
+    };
+
+    let compute_capability = ApiVersion::new(
+        get_attribute(CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?
+            .try_into()?,
+        get_attribute(CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?
+            .try_into()?,
+    );
+    let multiprocessor_count =
+        get_attribute(CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?
+            .try_into()?;
+    let integrated = get_attribute(CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_INTEGRATED)? != 0;
+    let tcc_driver = get_attribute(CUdevice_attribute_enum_CU_DEVICE_ATTRIBUTE_TCC_DRIVER)? != 0;
+
+    let mut total_memory = 0usize;
+    call_cuda_sym!(sym_cu_device_total_mem(&mut total_memory, device.handle));
+
+    // Encoded as 1000 * major + 10 * minor, e.g. 12040 for 12.4
+    let mut driver_version = 0;
+    call_cuda_sym!(sym_cu_driver_get_version(&mut driver_version));
+    let driver_version: u32 = driver_version.try_into()?;
+
+    let uuid = Some(device.uuid).filter(|it| !it.is_nil());
+    let nvml_index = nvml().and_then(|nvml| match nvml.device_index(uuid, pci_address) {
+        Ok(index) => Some(index),
+        Err(err) => {
+            log_debug!("Failed to query NVML index of {}: {}", pci_address, err);
+            None
+        }
+    });
+
+    Ok(CudaDeviceInfo {
+        uuid,
+        pci_address,
+        compute_capability,
+        total_memory: total_memory.try_into()?,
+        multiprocessor_count,
+        integrated,
+        tcc_driver,
+        driver_version: ApiVersion::new(driver_version / 1000, driver_version % 1000 / 10),
+        nvml_index,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::dylib::is_cuda_loaded;
@@ -118,4 +215,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_device_info() -> Result<(), NvidiaError> {
+        if !is_cuda_loaded() {
+            eprintln!("libcuda.so not available");
+            return Ok(());
+        }
+
+        let devices = enumerate_devices()?;
+        assert!(!devices.is_empty());
+
+        let device_info = get_device_info(devices.first().unwrap())?;
+        dbg!(&device_info);
+
+        assert!(device_info.total_memory > 0);
+        assert!(device_info.compute_capability.major > 0);
+
+        Ok(())
+    }
 }
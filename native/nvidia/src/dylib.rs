@@ -14,15 +14,19 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use lazy_static::lazy_static;
 use libloading::{Error, Library};
 
+use common::{log_debug, log_info};
 use dylib_types::*;
 
-use crate::sys::libcuviddec_sys::cudaError_enum_CUDA_SUCCESS;
-use crate::NvidiaError;
+use crate::sys::libcuviddec_sys::{cudaError_enum_CUDA_SUCCESS, CUresult};
+use crate::{InitFailure, NvidiaError};
 
 #[allow(non_camel_case_types, dead_code)]
 mod dylib_types {
@@ -33,18 +37,135 @@ mod dylib_types {
     pub type cuInit = unsafe extern "C" fn(c_uint: c_uint) -> CUresult;
 }
 
+/// Environment variable containing additional directories to search for the NVIDIA libraries,
+/// separated by `:` like `LD_LIBRARY_PATH`.
+pub const LIBRARY_PATH_ENV: &str = "ENC_DEC_HWSCAN_NVIDIA_LIBRARY_PATH";
+
+const LIBCUDA_NAMES: [&str; 2] = ["libcuda.so.1", "libcuda.so"];
+const LIBCUVIDDEC_NAMES: [&str; 2] = ["libnvcuvid.so.1", "libnvcuvid.so"];
+const LIBNV_ENCODE_NAMES: [&str; 2] = ["libnvidia-encode.so.1", "libnvidia-encode.so"];
+const LIBNV_OPTICAL_FLOW_NAMES: [&str; 2] =
+    ["libnvidia-opticalflow.so.1", "libnvidia-opticalflow.so"];
+const LIBNVML_NAMES: [&str; 2] = ["libnvidia-ml.so.1", "libnvidia-ml.so"];
+
+/// A library loaded by [load_library] along with the file it was loaded from.
+pub struct LoadedLibrary {
+    pub library: Library,
+    pub path: PathBuf,
+}
+
 lazy_static! {
-    static ref _LIBCUDA_RAW: Result<Library, Error> = unsafe { Library::new("libcuda.so") };
-    pub static ref LIBCUDA: Result<&'static Library, &'static Error> = _LIBCUDA_RAW.as_ref();
-    static ref _LIBCUVIDDEC_RAW: Result<Library, Error> = unsafe { Library::new("libnvcuvid.so") };
+    static ref SEARCH_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(
+        env::var_os(LIBRARY_PATH_ENV)
+            .map(|it| env::split_paths(&it).collect())
+            .unwrap_or_default()
+    );
+    static ref _LIBCUDA_RAW: Result<LoadedLibrary, Error> = load_library(&LIBCUDA_NAMES);
+    pub static ref LIBCUDA: Result<&'static Library, &'static Error> =
+        _LIBCUDA_RAW.as_ref().map(|it| &it.library);
+    static ref _LIBCUVIDDEC_RAW: Result<LoadedLibrary, Error> = load_library(&LIBCUVIDDEC_NAMES);
     pub static ref LIBCUVIDDEC: Result<&'static Library, &'static Error> =
-        _LIBCUVIDDEC_RAW.as_ref();
-    static ref _LIBNV_ENCODE_RAW: Result<Library, Error> =
-        unsafe { Library::new("libnvidia-encode.so") };
+        _LIBCUVIDDEC_RAW.as_ref().map(|it| &it.library);
+    static ref _LIBNV_ENCODE_RAW: Result<LoadedLibrary, Error> = load_library(&LIBNV_ENCODE_NAMES);
     pub static ref LIBNV_ENCODE: Result<&'static Library, &'static Error> =
-        _LIBNV_ENCODE_RAW.as_ref();
+        _LIBNV_ENCODE_RAW.as_ref().map(|it| &it.library);
+    static ref _LIBNV_OPTICAL_FLOW_RAW: Result<LoadedLibrary, Error> =
+        load_library(&LIBNV_OPTICAL_FLOW_NAMES);
+    /// Optional, only required to probe the optical flow accelerator.
+    pub static ref LIBNV_OPTICAL_FLOW: Result<&'static Library, &'static Error> =
+        _LIBNV_OPTICAL_FLOW_RAW.as_ref().map(|it| &it.library);
+    static ref _LIBNVML_RAW: Result<LoadedLibrary, Error> = load_library(&LIBNVML_NAMES);
+    /// Optional, only required to query telemetry and NVML indices.
+    pub static ref LIBNVML: Result<&'static Library, &'static Error> =
+        _LIBNVML_RAW.as_ref().map(|it| &it.library);
     pub static ref CUDA_INITIALIZED: Mutex<bool> = Mutex::new(false);
-    pub static ref CUDA_INIT_FAILED: Mutex<bool> = Mutex::new(false);
+    /// Status of the last failed `cuInit` call, cleared once it succeeds.
+    pub static ref CUDA_INIT_FAILED: Mutex<Option<CUresult>> = Mutex::new(None);
+}
+
+/// Adds `dirs` to the directories searched for the NVIDIA libraries, after those from
+/// [LIBRARY_PATH_ENV].
+///
+/// The libraries are loaded once per process, so this has no effect after the first scan.
+pub fn add_library_search_dirs(dirs: &[PathBuf]) {
+    let mut search_dirs = SEARCH_DIRS.lock().unwrap();
+
+    for dir in dirs {
+        if !search_dirs.contains(dir) {
+            search_dirs.push(dir.clone());
+        }
+    }
+}
+
+/// Returns the file each NVIDIA library was loaded from, or the error that prevented loading it,
+/// keyed by the preferred name of the library. The optical flow library and NVML are optional.
+pub fn libraries() -> [(&'static str, Result<&'static Path, &'static Error>); 5] {
+    [
+        (LIBCUDA_NAMES[0], _LIBCUDA_RAW.as_ref()),
+        (LIBCUVIDDEC_NAMES[0], _LIBCUVIDDEC_RAW.as_ref()),
+        (LIBNV_ENCODE_NAMES[0], _LIBNV_ENCODE_RAW.as_ref()),
+        (
+            LIBNV_OPTICAL_FLOW_NAMES[0],
+            _LIBNV_OPTICAL_FLOW_RAW.as_ref(),
+        ),
+        (LIBNVML_NAMES[0], _LIBNVML_RAW.as_ref()),
+    ]
+    .map(|(name, library)| (name, library.map(|it| it.path.as_path())))
+}
+
+/// Loads the first of `names` that can be found, trying the search directories before the
+/// directories known to the dynamic linker. Returns the first error if none could be loaded.
+fn load_library(names: &[&str]) -> Result<LoadedLibrary, Error> {
+    let search_dirs = SEARCH_DIRS.lock().unwrap().clone();
+
+    let candidates = search_dirs
+        .iter()
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .filter(|path| path.exists())
+        .chain(names.iter().map(PathBuf::from));
+
+    let mut first_error = None;
+
+    for candidate in candidates {
+        match unsafe { Library::new(&candidate) } {
+            Ok(library) => {
+                let path = if candidate.is_absolute() {
+                    candidate
+                } else {
+                    resolve_loaded_path(&candidate).unwrap_or(candidate)
+                };
+
+                log_debug!("Loaded {} from {}", names[0], path.display());
+                return Ok(LoadedLibrary { library, path });
+            }
+            Err(err) => {
+                log_debug!("Failed to load {}: {}", candidate.display(), err);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(first_error.expect("no library names specified"))
+}
+
+/// Finds the file the dynamic linker loaded for the bare library name `name` in the memory
+/// mappings of this process, e.g. `/usr/lib/x86_64-linux-gnu/libcuda.so.550.54.14` for
+/// `libcuda.so.1`.
+fn resolve_loaded_path(name: &Path) -> Option<PathBuf> {
+    let name = name.to_str()?;
+    let prefix = &name[..name.find(".so")? + 3];
+
+    fs::read_to_string("/proc/self/maps")
+        .ok()?
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .map(Path::new)
+        .find(|path| {
+            path.file_name()
+                .and_then(|it| it.to_str())
+                .is_some_and(|it| it.starts_with(prefix))
+        })
+        .map(Path::to_path_buf)
 }
 
 #[cfg(test)]
@@ -52,6 +173,11 @@ pub(crate) fn is_cuda_loaded() -> bool {
     (*LIBCUDA).is_ok()
 }
 
+/// Returns whether all required NVIDIA libraries could be loaded. Doesn't initialize CUDA.
+pub fn is_available() -> bool {
+    LIBCUDA.is_ok() && LIBCUVIDDEC.is_ok() && LIBNV_ENCODE.is_ok()
+}
+
 #[derive(Copy, Clone)]
 pub struct Libs {
     pub lib_cuda: &'static Library,
@@ -69,23 +195,38 @@ pub fn ensure_available() -> Result<Libs, NvidiaError> {
     })
 }
 
+/// Calls `cuInit` once per process. The lock is held while initializing, so threads probing
+/// devices concurrently wait for the first one instead of initializing the driver twice.
+///
+/// A failure isn't remembered as final, the next call tries again. This allows recovering from a
+/// driver that was reloaded, though libcuda may keep returning the same error until the process
+/// is restarted.
 pub fn cuda_init() -> Result<(), NvidiaError> {
     let mut init_handle = CUDA_INITIALIZED.lock().unwrap();
+    if *init_handle {
+        return Ok(());
+    }
 
-    if !*init_handle {
-        let sym_cu_init: libloading::Symbol<cuInit> = unsafe {
-            (*LIBCUDA)?
-                .get(b"cuInit\0")
-                .expect("cuInit not found in libcuda.so")
-        };
+    let lib_cuda = (*LIBCUDA)?;
+    let sym_cu_init = crate::get_sym!(lib_cuda, cuInit);
 
-        let init_result = unsafe { sym_cu_init(0) };
-        if init_result != cudaError_enum_CUDA_SUCCESS {
-            let mut init_failed_handle = CUDA_INIT_FAILED.lock().unwrap();
-            *init_failed_handle = true;
+    let mut init_failed_handle = CUDA_INIT_FAILED.lock().unwrap();
+    if let Some(status) = *init_failed_handle {
+        log_debug!("Retrying cuInit, it previously failed with {}", status);
+    }
 
-            return Err(NvidiaError::OperationFailed(init_result));
-        }
+    let init_result = unsafe { sym_cu_init(0) };
+    if init_result != cudaError_enum_CUDA_SUCCESS {
+        *init_failed_handle = Some(init_result);
+
+        return Err(NvidiaError::InitFailed(
+            InitFailure::from_status(init_result),
+            init_result,
+        ));
+    }
+
+    if init_failed_handle.take().is_some() {
+        log_info!("CUDA initialized after a previous failure");
     }
 
     *init_handle = true;
@@ -149,4 +290,16 @@ mod tests {
 
         assert!(cuda_init().is_ok())
     }
+
+    #[test]
+    fn libraries_report_loaded_file() {
+        if !is_cuda_loaded() {
+            eprintln!("libcuda.so not available");
+            return;
+        }
+
+        let (name, libcuda) = libraries()[0];
+        assert_eq!("libcuda.so.1", name);
+        assert!(libcuda.unwrap().is_absolute());
+    }
 }
@@ -21,13 +21,17 @@ use std::ptr;
 
 use uuid::Uuid;
 
+use common::ApiVersion;
 use dylib_types::*;
 
 use crate::dylib::{ensure_available, Libs};
 use crate::sys::libcuviddec_sys::CUcontext;
 use crate::sys::libnv_encode_api_sys::{
-    GUID, NV_ENCODE_API_FUNCTION_LIST, NV_ENC_CAPS_PARAM, NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS,
-    _NVENCSTATUS_NV_ENC_SUCCESS, _NV_ENC_DEVICE_TYPE_NV_ENC_DEVICE_TYPE_CUDA,
+    _NVENCSTATUS_NV_ENC_ERR_INCOMPATIBLE_CLIENT_KEY, _NVENCSTATUS_NV_ENC_ERR_OUT_OF_MEMORY,
+    _NVENCSTATUS_NV_ENC_SUCCESS, _NV_ENC_DEVICE_TYPE_NV_ENC_DEVICE_TYPE_CUDA, GUID,
+    NVENCAPI_MAJOR_VERSION, NVENCAPI_MINOR_VERSION, NV_ENCODE_API_FUNCTION_LIST,
+    NV_ENC_BUFFER_FORMAT, NV_ENC_CAPS_PARAM, NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS,
+    NV_ENC_PRESET_CONFIG, NV_ENC_TUNING_INFO,
 };
 use crate::NvidiaError;
 
@@ -37,23 +41,76 @@ mod dylib_types {
 
     pub type NvEncodeAPICreateInstance =
         unsafe extern "C" fn(*mut NV_ENCODE_API_FUNCTION_LIST) -> NVENCSTATUS;
+    pub type NvEncodeAPIGetMaxSupportedVersion = unsafe extern "C" fn(*mut u32) -> NVENCSTATUS;
 }
 
-const NVENCAPI_MAJOR_VERSION: u32 = 7;
-const NVENCAPI_MINOR_VERSION: u32 = 0;
+/// The API version of the bundled `nvEncodeAPI.h`, the highest version encode sessions are opened
+/// with.
+pub const HEADER_API_VERSION: ApiVersion =
+    ApiVersion::new(NVENCAPI_MAJOR_VERSION, NVENCAPI_MINOR_VERSION);
+
+/// The oldest API version encode sessions are opened with. The structs used to open a session and
+/// to query GUIDs and capabilities haven't changed their layout or revision since.
+pub const MIN_API_VERSION: ApiVersion = ApiVersion::new(7, 0);
+
+/// `NV_ENC_PRESET_CONFIG` and `NV_ENC_CONFIG` change with almost every API version, so preset
+/// configurations are only queried from sessions using the layout of the bundled header.
+pub const PRESET_CONFIG_API_VERSION: ApiVersion = HEADER_API_VERSION;
+
+/// The maximum number of encode sessions opened by [probe_max_sessions], so drivers without a
+/// session limit aren't probed until the GPU runs out of memory.
+pub const MAX_PROBED_SESSIONS: u32 = 32;
+
+// the struct revisions defined by the bundled nvEncodeAPI.h
+const NV_ENCODE_API_FUNCTION_LIST_REV: u32 = 2;
+const NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS_REV: u32 = 1;
+const NV_ENC_CAPS_PARAM_REV: u32 = 1;
+const NV_ENC_PRESET_CONFIG_REV: u32 = 5;
+const NV_ENC_CONFIG_REV: u32 = 9;
+
+/// Encodes `api_version` like `NVENCAPI_VERSION`.
+const fn nvencapi_version(api_version: ApiVersion) -> u32 {
+    api_version.major | (api_version.minor << 24)
+}
+
+/// Encodes the struct `revision` for `api_version` like `NVENCAPI_STRUCT_VERSION`.
+pub const fn nvencapi_struct_version(api_version: ApiVersion, revision: u32) -> u32 {
+    nvencapi_version(api_version) | (revision << 16) | (0x7 << 28)
+}
+
+/// Returns the highest NVENC API version supported by the installed driver.
+pub fn max_supported_version() -> Result<ApiVersion, NvidiaError> {
+    let Libs { lib_nv_encode, .. } = ensure_available()?;
+    let sym_nv_encode_api_get_max_supported_version =
+        get_sym!(lib_nv_encode, NvEncodeAPIGetMaxSupportedVersion);
+
+    let mut version = 0u32;
+    let nvencstatus = unsafe { sym_nv_encode_api_get_max_supported_version(&mut version) };
+    if nvencstatus != _NVENCSTATUS_NV_ENC_SUCCESS {
+        return Err(NvidiaError::OperationFailed(nvencstatus));
+    }
 
-const NVENCAPI_VERSION: u32 = NVENCAPI_MAJOR_VERSION | (NVENCAPI_MINOR_VERSION << 24);
+    // encoded as (major << 4) | minor
+    Ok(ApiVersion::new(version >> 4, version & 0xf))
+}
 
-pub const fn nvencapi_struct_version(version: u32) -> u32 {
-    NVENCAPI_VERSION | (version << 16) | (0x7 << 28)
+/// Returns the API version encode sessions are opened with if the driver supports up to
+/// `driver_api_version`, the lower of it and [HEADER_API_VERSION]. Fails with
+/// [NvidiaError::UnsupportedApiVersion] if the driver is older than [MIN_API_VERSION].
+pub fn negotiate_api_version(driver_api_version: ApiVersion) -> Result<ApiVersion, NvidiaError> {
+    if driver_api_version < MIN_API_VERSION {
+        return Err(NvidiaError::UnsupportedApiVersion(driver_api_version));
+    }
+
+    Ok(driver_api_version.min(HEADER_API_VERSION))
 }
 
-fn encode_api() -> Result<NV_ENCODE_API_FUNCTION_LIST, NvidiaError> {
+fn encode_api(api_version: ApiVersion) -> Result<NV_ENCODE_API_FUNCTION_LIST, NvidiaError> {
     let Libs { lib_nv_encode, .. } = ensure_available()?;
     let sym_nv_encode_api_create_instance = get_sym!(lib_nv_encode, NvEncodeAPICreateInstance);
 
     let mut instance_ptr: NV_ENCODE_API_FUNCTION_LIST = unsafe { zeroed() };
-    instance_ptr.version = nvencapi_struct_version(2);
+    instance_ptr.version = nvencapi_struct_version(api_version, NV_ENCODE_API_FUNCTION_LIST_REV);
 
     let nvencstatus = unsafe { sym_nv_encode_api_create_instance(&mut instance_ptr) };
     if nvencstatus != _NVENCSTATUS_NV_ENC_SUCCESS {
@@ -65,6 +122,7 @@ fn encode_api() -> Result<NV_ENCODE_API_FUNCTION_LIST, NvidiaError> {
 
 #[derive(Debug)]
 pub struct NvEncoder<'enc> {
+    api_version: ApiVersion,
     encode_api: NV_ENCODE_API_FUNCTION_LIST,
     handle: *mut c_void,
     _context: &'enc CUcontext,
@@ -108,16 +166,20 @@ macro_rules! call_encoder_fn {
 }
 
 impl<'a> NvEncoder<'a> {
-    pub fn new(context: &'a mut CUcontext) -> Result<NvEncoder<'a>, NvidiaError> {
-        let encode_api = encode_api()?;
+    /// Opens an encode session using the API version negotiated with the driver, see
+    /// [negotiate_api_version].
+    pub fn new(context: &'a CUcontext) -> Result<NvEncoder<'a>, NvidiaError> {
+        let api_version = negotiate_api_version(max_supported_version()?)?;
+        let encode_api = encode_api(api_version)?;
 
         let mut handle = ptr::null_mut();
 
         let mut open_params_ptr: NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS = unsafe { zeroed() };
-        open_params_ptr.version = nvencapi_struct_version(1);
+        open_params_ptr.version =
+            nvencapi_struct_version(api_version, NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS_REV);
         open_params_ptr.deviceType = _NV_ENC_DEVICE_TYPE_NV_ENC_DEVICE_TYPE_CUDA;
         open_params_ptr.device = *context as *mut c_void;
-        open_params_ptr.apiVersion = NVENCAPI_VERSION;
+        open_params_ptr.apiVersion = nvencapi_version(api_version);
 
         match encode_api.nvEncOpenEncodeSessionEx {
             Some(func) => unsafe {
@@ -126,7 +188,18 @@ impl<'a> NvEncoder<'a> {
                     // inlined the macro because of this, nvEncodeAPI.h requires us to destroy
                     // the encoder even when the initial creation failed
                     encode_api.nvEncDestroyEncoder.unwrap()(handle);
-                    return Err(NvidiaError::OperationFailed(nvencstatus));
+
+                    // only the return value of this call means the session limit is reached,
+                    // the same values have other meanings elsewhere
+                    return Err(
+                        if nvencstatus == _NVENCSTATUS_NV_ENC_ERR_OUT_OF_MEMORY
+                            || nvencstatus == _NVENCSTATUS_NV_ENC_ERR_INCOMPATIBLE_CLIENT_KEY
+                        {
+                            NvidiaError::SessionLimit(nvencstatus)
+                        } else {
+                            NvidiaError::OperationFailed(nvencstatus)
+                        },
+                    );
                 }
             },
             None => {
@@ -137,12 +210,18 @@ impl<'a> NvEncoder<'a> {
         }
 
         Ok(NvEncoder {
+            api_version,
             encode_api,
             handle,
             _context: context,
         })
     }
 
+    /// The API version the session was opened with.
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
     pub fn get_encode_guids(&self) -> Result<Vec<Uuid>, NvidiaError> {
         let mut guid_count = 0u32;
         call_encoder_fn!(self, nvEncGetEncodeGUIDCount(&mut guid_count));
@@ -174,7 +253,7 @@ impl<'a> NvEncoder<'a> {
 
     pub fn get_encode_caps(&self, codec_guid: &Uuid, cap: u32) -> Result<i32, NvidiaError> {
         let mut enc_caps_param: NV_ENC_CAPS_PARAM = unsafe { zeroed() };
-        enc_caps_param.version = nvencapi_struct_version(1);
+        enc_caps_param.version = nvencapi_struct_version(self.api_version, NV_ENC_CAPS_PARAM_REV);
         enc_caps_param.capsToQuery = cap as c_uint;
 
         let mut caps_val = 0i32;
@@ -185,6 +264,102 @@ impl<'a> NvEncoder<'a> {
 
         Ok(caps_val)
     }
+
+    pub fn get_encode_preset_guids(&self, codec_guid: &Uuid) -> Result<Vec<Uuid>, NvidiaError> {
+        let mut guid_count = 0u32;
+        call_encoder_fn!(
+            self,
+            nvEncGetEncodePresetCount(codec_guid.into(), &mut guid_count)
+        );
+
+        let guids_ptr = alloc_guid_array(guid_count);
+        call_encoder_fn!(
+            self,
+            nvEncGetEncodePresetGUIDs(codec_guid.into(), guids_ptr, guid_count, &mut guid_count)
+        );
+
+        make_uuid_vec(guids_ptr, guid_count as usize)
+    }
+
+    /// Returns the input buffer formats the encoder accepts for `codec_guid`.
+    pub fn get_input_formats(
+        &self,
+        codec_guid: &Uuid,
+    ) -> Result<Vec<NV_ENC_BUFFER_FORMAT>, NvidiaError> {
+        let mut format_count = 0u32;
+        call_encoder_fn!(
+            self,
+            nvEncGetInputFormatCount(codec_guid.into(), &mut format_count)
+        );
+
+        let mut formats = vec![0 as NV_ENC_BUFFER_FORMAT; format_count as usize];
+        call_encoder_fn!(
+            self,
+            nvEncGetInputFormats(
+                codec_guid.into(),
+                formats.as_mut_ptr(),
+                format_count,
+                &mut format_count
+            )
+        );
+
+        formats.truncate(format_count as usize);
+        Ok(formats)
+    }
+
+    /// Returns the default configuration of `preset_guid` tuned for `tuning_info`. The result
+    /// uses the layout of the bundled header, so this must only be called if the session was
+    /// opened with [PRESET_CONFIG_API_VERSION].
+    pub fn get_encode_preset_config(
+        &self,
+        codec_guid: &Uuid,
+        preset_guid: &Uuid,
+        tuning_info: NV_ENC_TUNING_INFO,
+    ) -> Result<NV_ENC_PRESET_CONFIG, NvidiaError> {
+        let mut preset_config: NV_ENC_PRESET_CONFIG = unsafe { zeroed() };
+        preset_config.version =
+            nvencapi_struct_version(self.api_version, NV_ENC_PRESET_CONFIG_REV) | (1 << 31);
+        preset_config.presetCfg.version =
+            nvencapi_struct_version(self.api_version, NV_ENC_CONFIG_REV) | (1 << 31);
+
+        call_encoder_fn!(
+            self,
+            nvEncGetEncodePresetConfigEx(
+                codec_guid.into(),
+                preset_guid.into(),
+                tuning_info,
+                &mut preset_config
+            )
+        );
+
+        Ok(preset_config)
+    }
+}
+
+/// Returns whether `error` was caused by the driver refusing another encode session, e.g. because
+/// all sessions of a consumer GPU are in use.
+pub fn is_session_limit_reached(error: &NvidiaError) -> bool {
+    matches!(error, NvidiaError::SessionLimit(_))
+}
+
+/// Determines how many encode sessions can be opened concurrently on `context` by opening sessions
+/// until the driver refuses another one. All sessions are released again before returning.
+///
+/// Sessions held by other processes count against the limit, so on a GPU that is in use this is
+/// the number of sessions still available. Probing stops at [MAX_PROBED_SESSIONS], which is
+/// returned if the driver doesn't limit the number of sessions.
+pub fn probe_max_sessions(context: &CUcontext) -> Result<u32, NvidiaError> {
+    let mut sessions = Vec::new();
+
+    while sessions.len() < MAX_PROBED_SESSIONS as usize {
+        match NvEncoder::new(context) {
+            Ok(encoder) => sessions.push(encoder),
+            Err(err) if is_session_limit_reached(&err) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(sessions.len() as u32)
 }
 
 fn alloc_guid_array(guid_count: u32) -> *mut GUID {
@@ -225,6 +400,11 @@ pub mod guid {
         0x54,
     ]);
 
+    pub const CODEC_PROFILE_AUTOSELECT: Uuid = Uuid::from_bytes([
+        0xbf, 0xd6, 0xf8, 0xe7, 0x23, 0x3c, 0x43, 0x41, 0x8b, 0x3e, 0x48, 0x18, 0x52, 0x38, 0x03,
+        0xf4,
+    ]);
+
     pub const H264_PROFILE_BASELINE: Uuid = Uuid::from_bytes([
         0x07, 0x27, 0xbc, 0xaa, 0x78, 0xc4, 0x4c, 0x83, 0x8c, 0x2f, 0xef, 0x3d, 0xff, 0x26, 0x7c,
         0x6a,
@@ -241,6 +421,18 @@ pub mod guid {
         0x7a, 0xc6, 0x63, 0xcb, 0xa5, 0x98, 0x49, 0x60, 0xb8, 0x44, 0x33, 0x9b, 0x26, 0x1a, 0x7d,
         0x52,
     ]);
+    pub const H264_PROFILE_STEREO: Uuid = Uuid::from_bytes([
+        0x40, 0x84, 0x7b, 0xf5, 0x33, 0xf7, 0x46, 0x01, 0x90, 0x84, 0xe8, 0xfe, 0x3c, 0x1d, 0xb8,
+        0xb7,
+    ]);
+    pub const H264_PROFILE_PROGRESSIVE_HIGH: Uuid = Uuid::from_bytes([
+        0xb4, 0x05, 0xaf, 0xac, 0xf3, 0x2b, 0x41, 0x7b, 0x89, 0xc4, 0x9a, 0xbe, 0xed, 0x3e, 0x59,
+        0x78,
+    ]);
+    pub const H264_PROFILE_CONSTRAINED_HIGH: Uuid = Uuid::from_bytes([
+        0xae, 0xc1, 0xbd, 0x87, 0xe8, 0x5b, 0x48, 0xf2, 0x84, 0xc3, 0x98, 0xbc, 0xa6, 0x28, 0x50,
+        0x72,
+    ]);
 
     pub const HEVC_PROFILE_MAIN: Uuid = Uuid::from_bytes([
         0xb5, 0x14, 0xc3, 0x9a, 0xb5, 0x5b, 0x40, 0xfa, 0x87, 0x8f, 0xf1, 0x25, 0x3b, 0x4d, 0xfd,
@@ -250,12 +442,45 @@ pub mod guid {
         0xfa, 0x4d, 0x2b, 0x6c, 0x3a, 0x5b, 0x41, 0x1a, 0x80, 0x18, 0x0a, 0x3f, 0x5e, 0x3c, 0x9b,
         0xe5,
     ]);
+    pub const HEVC_PROFILE_FREXT: Uuid = Uuid::from_bytes([
+        0x51, 0xec, 0x32, 0xb5, 0x1b, 0x4c, 0x45, 0x3c, 0x9c, 0xbd, 0xb6, 0x16, 0xbd, 0x62, 0x13,
+        0x41,
+    ]);
 
     pub const AV1_PROFILE_MAIN: Uuid = Uuid::from_bytes([
         0x5f, 0x2a, 0x39, 0xf5, 0xf1, 0x4e, 0x4f, 0x95, 0x9a, 0x9e, 0xb7, 0x6d, 0x56, 0x8f, 0xcf,
         0x97,
     ]);
 
+    pub const PRESET_P1: Uuid = Uuid::from_bytes([
+        0xfc, 0x0a, 0x8d, 0x3e, 0x45, 0xf8, 0x4c, 0xf8, 0x80, 0xc7, 0x29, 0x88, 0x71, 0x59, 0x0e,
+        0xbf,
+    ]);
+    pub const PRESET_P2: Uuid = Uuid::from_bytes([
+        0xf5, 0x81, 0xcf, 0xb8, 0x88, 0xd6, 0x43, 0x81, 0x93, 0xf0, 0xdf, 0x13, 0xf9, 0xc2, 0x7d,
+        0xab,
+    ]);
+    pub const PRESET_P3: Uuid = Uuid::from_bytes([
+        0x36, 0x85, 0x01, 0x10, 0x3a, 0x07, 0x44, 0x1f, 0x94, 0xd5, 0x36, 0x70, 0x63, 0x1f, 0x91,
+        0xf6,
+    ]);
+    pub const PRESET_P4: Uuid = Uuid::from_bytes([
+        0x90, 0xa7, 0xb8, 0x26, 0xdf, 0x06, 0x48, 0x62, 0xb9, 0xd2, 0xcd, 0x6d, 0x73, 0xa0, 0x86,
+        0x81,
+    ]);
+    pub const PRESET_P5: Uuid = Uuid::from_bytes([
+        0x21, 0xc6, 0xe6, 0xb4, 0x29, 0x7a, 0x4c, 0xba, 0x99, 0x8f, 0xb6, 0xcb, 0xde, 0x72, 0xad,
+        0xe3,
+    ]);
+    pub const PRESET_P6: Uuid = Uuid::from_bytes([
+        0x8e, 0x75, 0xc2, 0x79, 0x62, 0x99, 0x4a, 0xb6, 0x83, 0x02, 0x0b, 0x21, 0x5a, 0x33, 0x5c,
+        0xf5,
+    ]);
+    pub const PRESET_P7: Uuid = Uuid::from_bytes([
+        0x84, 0x84, 0x8c, 0x12, 0x6f, 0x71, 0x4c, 0x13, 0x93, 0x1b, 0x53, 0xe2, 0x83, 0xf5, 0x79,
+        0x74,
+    ]);
+
     impl TryFrom<GUID> for Uuid {
         type Error = uuid::Error;
 
@@ -299,6 +524,54 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_negotiate_api_version() {
+        assert_eq!(
+            negotiate_api_version(ApiVersion::new(13, 0)).unwrap(),
+            HEADER_API_VERSION
+        );
+        assert_eq!(
+            negotiate_api_version(HEADER_API_VERSION).unwrap(),
+            HEADER_API_VERSION
+        );
+        assert_eq!(
+            negotiate_api_version(ApiVersion::new(11, 1)).unwrap(),
+            ApiVersion::new(11, 1)
+        );
+        assert_eq!(
+            negotiate_api_version(MIN_API_VERSION).unwrap(),
+            MIN_API_VERSION
+        );
+        assert!(matches!(
+            negotiate_api_version(ApiVersion::new(6, 0)),
+            Err(NvidiaError::UnsupportedApiVersion(version)) if version == ApiVersion::new(6, 0)
+        ));
+    }
+
+    #[test]
+    fn test_nvencapi_struct_version() {
+        // NV_ENC_CAPS_PARAM_VER of the bundled header
+        assert_eq!(
+            nvencapi_struct_version(ApiVersion::new(12, 2), NV_ENC_CAPS_PARAM_REV),
+            0x7201000c
+        );
+        assert_eq!(
+            nvencapi_struct_version(ApiVersion::new(11, 1), NV_ENC_CAPS_PARAM_REV),
+            0x7101000b
+        );
+    }
+
+    #[test]
+    fn test_is_session_limit_reached() {
+        assert!(is_session_limit_reached(&NvidiaError::SessionLimit(
+            _NVENCSTATUS_NV_ENC_ERR_OUT_OF_MEMORY
+        )));
+        // e.g. a CUresult of a context operation in the same closure
+        assert!(!is_session_limit_reached(&NvidiaError::OperationFailed(
+            _NVENCSTATUS_NV_ENC_ERR_OUT_OF_MEMORY
+        )));
+    }
+
     #[test]
     fn test_get_encode_guids() -> Result<(), NvidiaError> {
         if !is_cuda_loaded() {
@@ -313,6 +586,7 @@ mod tests {
 
         context.with_floating_ctx(|context| {
             let encoder = NvEncoder::new(context)?;
+
             let encode_guids = encoder.get_encode_guids()?;
 
             dbg!(&encode_guids);
@@ -324,9 +598,43 @@ mod tests {
             dbg!(&encode_profile_guids);
             assert!(!encode_profile_guids.is_empty());
 
+            let encode_preset_guids =
+                encoder.get_encode_preset_guids(encode_guids.first().unwrap())?;
+
+            dbg!(&encode_preset_guids);
+            assert!(!encode_preset_guids.is_empty());
+
+            let input_formats = encoder.get_input_formats(encode_guids.first().unwrap())?;
+
+            dbg!(&input_formats);
+            assert!(!input_formats.is_empty());
+
             Ok(())
         })?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_probe_max_sessions() -> Result<(), NvidiaError> {
+        if !is_cuda_loaded() {
+            eprintln!("libcuda.so not available");
+            return Ok(());
+        }
+
+        let devices = enumerate_devices()?;
+        assert!(!devices.is_empty());
+
+        let context = CudaContext::new(devices.first().unwrap())?;
+
+        let max_sessions = context.with_floating_ctx(|context| probe_max_sessions(context))?;
+
+        dbg!(max_sessions);
+        assert!(max_sessions > 0 && max_sessions <= MAX_PROBED_SESSIONS);
+
+        // all probed sessions must have been released again
+        context.with_floating_ctx(|context| NvEncoder::new(context).map(|_| ()))?;
+
+        Ok(())
+    }
 }
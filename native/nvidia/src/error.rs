@@ -15,10 +15,25 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 use std::ffi::c_uint;
+use std::fmt::{Display, Formatter};
 use std::num::TryFromIntError;
 
 use thiserror::Error;
 
+use common::ApiVersion;
+
+use crate::encoder::MIN_API_VERSION;
+use crate::sys::libcuviddec_sys::{
+    cudaError_enum_CUDA_ERROR_INSUFFICIENT_DRIVER, cudaError_enum_CUDA_ERROR_NO_DEVICE, CUresult,
+};
+
+// These results were added in CUDA 10.0 (803, 804) and 11.x (34, 46), defined here so the crate
+// still builds against the headers of older toolkits.
+const CUDA_ERROR_STUB_LIBRARY: CUresult = 34;
+const CUDA_ERROR_DEVICE_UNAVAILABLE: CUresult = 46;
+const CUDA_ERROR_SYSTEM_DRIVER_MISMATCH: CUresult = 803;
+const CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE: CUresult = 804;
+
 #[derive(Error, Debug)]
 pub enum NvidiaError {
     #[error("Nvidia driver not available: {0}")]
@@ -33,4 +48,90 @@ pub enum NvidiaError {
     FailedToConvertUuid(#[from] uuid::Error),
     #[error("Failed to convert result value: {0}")]
     FailedToConvertResult(#[from] TryFromIntError),
+    #[error("Failed to parse PCI bus id: {0}")]
+    FailedToParsePciBusId(String),
+    #[error("NVML call failed: {0}")]
+    NvmlFailed(c_uint),
+    #[error("Optical flow call failed: {0}")]
+    OpticalFlowFailed(c_uint),
+    #[error("Failed to initialize CUDA: {0} ({1})")]
+    InitFailed(InitFailure, c_uint),
+    #[error("Driver refused another encode session: {0}")]
+    SessionLimit(c_uint),
+    #[error(
+        "Driver only supports NVENC API {0}, at least {} is required",
+        MIN_API_VERSION
+    )]
+    UnsupportedApiVersion(ApiVersion),
+}
+
+/// Why `cuInit` failed, derived from the status it returned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InitFailure {
+    NoDevice,
+    InsufficientDriver,
+    DriverMismatch,
+    DeviceUnavailable,
+    Other,
+}
+
+impl InitFailure {
+    pub fn from_status(status: CUresult) -> Self {
+        #[allow(non_upper_case_globals)]
+        match status {
+            cudaError_enum_CUDA_ERROR_NO_DEVICE => InitFailure::NoDevice,
+            cudaError_enum_CUDA_ERROR_INSUFFICIENT_DRIVER
+            | CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE => InitFailure::InsufficientDriver,
+            CUDA_ERROR_SYSTEM_DRIVER_MISMATCH | CUDA_ERROR_STUB_LIBRARY => {
+                InitFailure::DriverMismatch
+            }
+            CUDA_ERROR_DEVICE_UNAVAILABLE => InitFailure::DeviceUnavailable,
+            _ => InitFailure::Other,
+        }
+    }
+}
+
+impl Display for InitFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            InitFailure::NoDevice => "no NVIDIA device found",
+            InitFailure::InsufficientDriver => "the installed driver is too old",
+            InitFailure::DriverMismatch => "libcuda doesn't match the kernel driver",
+            InitFailure::DeviceUnavailable => "the device is busy or unavailable",
+            InitFailure::Other => "unknown error",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_failure_from_status() {
+        assert_eq!(
+            InitFailure::from_status(cudaError_enum_CUDA_ERROR_NO_DEVICE),
+            InitFailure::NoDevice
+        );
+        assert_eq!(
+            InitFailure::from_status(CUDA_ERROR_SYSTEM_DRIVER_MISMATCH),
+            InitFailure::DriverMismatch
+        );
+        assert_eq!(InitFailure::from_status(999), InitFailure::Other);
+
+        let error = NvidiaError::InitFailed(InitFailure::NoDevice, 100);
+        assert_eq!(
+            error.to_string(),
+            "Failed to initialize CUDA: no NVIDIA device found (100)"
+        );
+
+        let error = NvidiaError::UnsupportedApiVersion(ApiVersion::new(6, 0));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Driver only supports NVENC API 6.0, at least {} is required",
+                MIN_API_VERSION
+            )
+        );
+    }
 }
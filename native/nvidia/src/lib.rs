@@ -14,6 +14,7 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+pub use dylib::{add_library_search_dirs, is_available, libraries, LIBRARY_PATH_ENV};
 pub use error::*;
 
 mod error;
@@ -24,3 +25,5 @@ pub mod caps;
 pub mod context;
 pub mod device;
 pub mod encoder;
+pub mod nvml;
+pub mod optical_flow;
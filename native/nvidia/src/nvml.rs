@@ -0,0 +1,375 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::ffi::{c_uint, CString};
+use std::ptr;
+
+use lazy_static::lazy_static;
+use libloading::Library;
+use uuid::Uuid;
+
+use common::owned::Telemetry;
+use common::{log_debug, PciAddress};
+use dylib_types::*;
+
+use crate::dylib::LIBNVML;
+use crate::NvidiaError;
+
+#[allow(non_camel_case_types, non_snake_case, dead_code)]
+mod dylib_types {
+    use std::ffi::{c_char, c_uint, c_ulonglong};
+
+    pub type nvmlReturn_t = c_uint;
+
+    pub const NVML_SUCCESS: nvmlReturn_t = 0;
+    pub const NVML_TEMPERATURE_GPU: c_uint = 0;
+
+    #[repr(C)]
+    pub struct nvmlDevice_st {
+        _unused: [u8; 0],
+    }
+
+    pub type nvmlDevice_t = *mut nvmlDevice_st;
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct nvmlUtilization_t {
+        pub gpu: c_uint,
+        pub memory: c_uint,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct nvmlMemory_t {
+        pub total: c_ulonglong,
+        pub free: c_ulonglong,
+        pub used: c_ulonglong,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    pub struct nvmlEncoderSessionInfo_t {
+        pub sessionId: c_uint,
+        pub pid: c_uint,
+        pub vgpuInstance: c_uint,
+        pub codecType: c_uint,
+        pub hResolution: c_uint,
+        pub vResolution: c_uint,
+        pub averageFps: c_uint,
+        pub averageLatency: c_uint,
+    }
+
+    pub type nvmlInit_v2 = unsafe extern "C" fn() -> nvmlReturn_t;
+    pub type nvmlDeviceGetHandleByUUID =
+        unsafe extern "C" fn(*const c_char, *mut nvmlDevice_t) -> nvmlReturn_t;
+    pub type nvmlDeviceGetHandleByPciBusId_v2 =
+        unsafe extern "C" fn(*const c_char, *mut nvmlDevice_t) -> nvmlReturn_t;
+    pub type nvmlDeviceGetUtilizationRates =
+        unsafe extern "C" fn(nvmlDevice_t, *mut nvmlUtilization_t) -> nvmlReturn_t;
+    pub type nvmlDeviceGetEncoderUtilization =
+        unsafe extern "C" fn(nvmlDevice_t, *mut c_uint, *mut c_uint) -> nvmlReturn_t;
+    pub type nvmlDeviceGetDecoderUtilization =
+        unsafe extern "C" fn(nvmlDevice_t, *mut c_uint, *mut c_uint) -> nvmlReturn_t;
+    pub type nvmlDeviceGetEncoderSessions = unsafe extern "C" fn(
+        nvmlDevice_t,
+        *mut c_uint,
+        *mut nvmlEncoderSessionInfo_t,
+    ) -> nvmlReturn_t;
+    pub type nvmlDeviceGetTemperature =
+        unsafe extern "C" fn(nvmlDevice_t, c_uint, *mut c_uint) -> nvmlReturn_t;
+    pub type nvmlDeviceGetPowerUsage =
+        unsafe extern "C" fn(nvmlDevice_t, *mut c_uint) -> nvmlReturn_t;
+    pub type nvmlDeviceGetMemoryInfo =
+        unsafe extern "C" fn(nvmlDevice_t, *mut nvmlMemory_t) -> nvmlReturn_t;
+    pub type nvmlDeviceGetIndex = unsafe extern "C" fn(nvmlDevice_t, *mut c_uint) -> nvmlReturn_t;
+}
+
+lazy_static! {
+    static ref NVML: Option<Nvml> = match *LIBNVML {
+        Ok(lib) => match Nvml::init(lib) {
+            Ok(nvml) => Some(nvml),
+            Err(err) => {
+                log_debug!("Failed to initialize NVML: {}", err);
+                None
+            }
+        },
+        Err(err) => {
+            log_debug!("NVML not available: {}", err);
+            None
+        }
+    };
+}
+
+/// Returns the system's NVML library, loading and initializing it on first use. It's searched
+/// like the other NVIDIA libraries, see [crate::dylib::add_library_search_dirs]. Returns `None`
+/// if it isn't installed or fails to initialize.
+pub fn nvml() -> Option<&'static Nvml> {
+    NVML.as_ref()
+}
+
+macro_rules! call_nvml_sym {
+    ($call: expr) => {{
+        let nvml_return = unsafe { $call };
+        if nvml_return != NVML_SUCCESS {
+            return Err(NvidiaError::NvmlFailed(nvml_return));
+        }
+    }};
+}
+
+/// A loaded and initialized NVML library, used to query runtime telemetry of NVIDIA devices.
+///
+/// NVML is never shut down again, as it's reference counted and shared with the rest of the
+/// process.
+pub struct Nvml {
+    lib: &'static Library,
+}
+
+impl Nvml {
+    /// Initializes the loaded NVML library `lib`.
+    pub fn init(lib: &'static Library) -> Result<Nvml, NvidiaError> {
+        let sym_nvml_init = get_sym!(lib, nvmlInit_v2);
+        call_nvml_sym!(sym_nvml_init());
+
+        Ok(Nvml { lib })
+    }
+
+    /// Queries the current telemetry of the device identified by `uuid` or, if it's not known,
+    /// `pci_address`. Values the driver doesn't report are left empty.
+    pub fn telemetry(
+        &self,
+        uuid: Option<Uuid>,
+        pci_address: PciAddress,
+    ) -> Result<Telemetry, NvidiaError> {
+        let device = self.device_handle(uuid, pci_address)?;
+
+        let mut telemetry = Telemetry::default();
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetUtilizationRates) {
+            let mut utilization = nvmlUtilization_t::default();
+            if unsafe { sym(device, &mut utilization) } == NVML_SUCCESS {
+                telemetry.gpu_utilization = Some(utilization.gpu);
+            }
+        }
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetEncoderUtilization) {
+            let (mut utilization, mut sampling_period) = (0, 0);
+            if unsafe { sym(device, &mut utilization, &mut sampling_period) } == NVML_SUCCESS {
+                telemetry.encoder_utilization = Some(utilization);
+            }
+        }
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetDecoderUtilization) {
+            let (mut utilization, mut sampling_period) = (0, 0);
+            if unsafe { sym(device, &mut utilization, &mut sampling_period) } == NVML_SUCCESS {
+                telemetry.decoder_utilization = Some(utilization);
+            }
+        }
+
+        if let Some(sessions) = self.encoder_sessions(device) {
+            telemetry.encoder_sessions = Some(sessions.len() as u32);
+
+            if !sessions.is_empty() {
+                let count = sessions.len() as u64;
+                let sum = |f: fn(&nvmlEncoderSessionInfo_t) -> c_uint| {
+                    sessions.iter().map(|it| u64::from(f(it))).sum::<u64>()
+                };
+
+                telemetry.encoder_average_fps = Some((sum(|it| it.averageFps) / count) as u32);
+                telemetry.encoder_average_latency =
+                    Some((sum(|it| it.averageLatency) / count) as u32);
+            }
+        }
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetTemperature) {
+            let mut temperature = 0;
+            if unsafe { sym(device, NVML_TEMPERATURE_GPU, &mut temperature) } == NVML_SUCCESS {
+                telemetry.temperature = Some(temperature);
+            }
+        }
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetPowerUsage) {
+            let mut power_usage = 0;
+            if unsafe { sym(device, &mut power_usage) } == NVML_SUCCESS {
+                telemetry.power_usage = Some(power_usage);
+            }
+        }
+
+        if let Some(sym) = get_sym_opt!(self.lib, nvmlDeviceGetMemoryInfo) {
+            let mut memory = nvmlMemory_t::default();
+            if unsafe { sym(device, &mut memory) } == NVML_SUCCESS {
+                telemetry.memory_total = Some(memory.total);
+                telemetry.memory_used = Some(memory.used);
+            }
+        }
+
+        Ok(telemetry)
+    }
+
+    /// Returns the index NVML and `nvidia-smi` use for the device identified by `uuid` or, if it's
+    /// not known, `pci_address`. Unlike CUDA ordinals it isn't affected by `CUDA_VISIBLE_DEVICES`.
+    pub fn device_index(
+        &self,
+        uuid: Option<Uuid>,
+        pci_address: PciAddress,
+    ) -> Result<u32, NvidiaError> {
+        let device = self.device_handle(uuid, pci_address)?;
+        let sym_get_index = get_sym!(self.lib, nvmlDeviceGetIndex);
+
+        let mut index = 0;
+        call_nvml_sym!(sym_get_index(device, &mut index));
+
+        Ok(index)
+    }
+
+    fn device_handle(
+        &self,
+        uuid: Option<Uuid>,
+        pci_address: PciAddress,
+    ) -> Result<nvmlDevice_t, NvidiaError> {
+        let mut device: nvmlDevice_t = ptr::null_mut();
+
+        match uuid {
+            Some(uuid) => {
+                let sym_get_handle = get_sym!(self.lib, nvmlDeviceGetHandleByUUID);
+                let uuid = CString::new(format!("GPU-{}", uuid)).unwrap();
+
+                call_nvml_sym!(sym_get_handle(uuid.as_ptr(), &mut device));
+            }
+            None => {
+                let sym_get_handle = get_sym!(self.lib, nvmlDeviceGetHandleByPciBusId_v2);
+                let pci_bus_id = CString::new(pci_address.to_string()).unwrap();
+
+                call_nvml_sym!(sym_get_handle(pci_bus_id.as_ptr(), &mut device));
+            }
+        }
+
+        Ok(device)
+    }
+
+    /// Returns the active encoder sessions, the first call only determines their count.
+    fn encoder_sessions(&self, device: nvmlDevice_t) -> Option<Vec<nvmlEncoderSessionInfo_t>> {
+        let sym = get_sym_opt!(self.lib, nvmlDeviceGetEncoderSessions)?;
+
+        let mut count = 0;
+        if unsafe { sym(device, &mut count, ptr::null_mut()) } != NVML_SUCCESS {
+            return None;
+        }
+
+        let mut sessions = vec![nvmlEncoderSessionInfo_t::default(); count as usize];
+        if count > 0 && unsafe { sym(device, &mut count, sessions.as_mut_ptr()) } != NVML_SUCCESS {
+            return None;
+        }
+
+        sessions.truncate(count as usize);
+
+        Some(sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::str::FromStr;
+
+    use super::*;
+
+    const STUB_UUID: &str = "4a8e7f0c-1d2b-4c3a-9e5f-6a7b8c9d0e1f";
+
+    /// Compiles `tests/stubs/libnvidia-ml.c` into a shared library named after `test`, returns
+    /// `None` if there is no C compiler.
+    fn build_stub(test: &str) -> Option<PathBuf> {
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/stubs/libnvidia-ml.c");
+        let target = std::env::temp_dir().join(format!(
+            "enc-dec-hwscan-nvml-stub-{}-{}.so",
+            test,
+            std::process::id()
+        ));
+
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&target)
+            .arg(&source)
+            .status()
+            .ok()?;
+
+        status.success().then_some(target)
+    }
+
+    /// Loads the stub built by [build_stub], the library is leaked like the system's NVML.
+    fn open_stub(stub: &Path) -> Result<Nvml, NvidiaError> {
+        let lib = unsafe { Library::new(stub) }.expect("failed to load NVML stub");
+        Nvml::init(Box::leak(Box::new(lib)))
+    }
+
+    #[test]
+    fn test_telemetry() -> Result<(), NvidiaError> {
+        let Some(stub) = build_stub("telemetry") else {
+            eprintln!("cc not available");
+            return Ok(());
+        };
+
+        let nvml = open_stub(&stub)?;
+
+        let expected = Telemetry {
+            gpu_utilization: Some(42),
+            encoder_utilization: Some(17),
+            decoder_utilization: Some(23),
+            encoder_sessions: Some(2),
+            encoder_average_fps: Some(45),
+            encoder_average_latency: Some(2000),
+            temperature: Some(55),
+            power_usage: Some(120000),
+            memory_total: Some(8 << 30),
+            memory_used: Some(1 << 30),
+        };
+
+        let uuid = Uuid::from_str(STUB_UUID).unwrap();
+        assert_eq!(nvml.telemetry(Some(uuid), PciAddress::default())?, expected);
+
+        let pci_address = PciAddress::from_str("0000:65:00.0").unwrap();
+        assert_eq!(nvml.telemetry(None, pci_address)?, expected);
+
+        assert!(matches!(
+            nvml.telemetry(Some(Uuid::nil()), pci_address),
+            Err(NvidiaError::NvmlFailed(_))
+        ));
+
+        let _ = std::fs::remove_file(stub);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_index() -> Result<(), NvidiaError> {
+        let Some(stub) = build_stub("device-index") else {
+            eprintln!("cc not available");
+            return Ok(());
+        };
+
+        let nvml = open_stub(&stub)?;
+
+        let uuid = Uuid::from_str(STUB_UUID).unwrap();
+        assert_eq!(nvml.device_index(Some(uuid), PciAddress::default())?, 3);
+
+        let pci_address = PciAddress::from_str("0000:65:00.0").unwrap();
+        assert_eq!(nvml.device_index(None, pci_address)?, 3);
+
+        let _ = std::fs::remove_file(stub);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::mem::zeroed;
+use std::ptr;
+
+use common::log_debug;
+use common::owned::OpticalFlowCaps;
+use dylib_types::*;
+
+use crate::dylib::LIBNV_OPTICAL_FLOW;
+use crate::sys::libcuviddec_sys::CUcontext;
+use crate::NvidiaError;
+
+/// Transcribed from `nvOpticalFlowCommon.h` and `nvOpticalFlowCuda.h` of the Optical Flow SDK,
+/// which isn't bundled. Only the functions required to query the capabilities are typed.
+#[allow(
+    non_camel_case_types,
+    non_snake_case,
+    dead_code,
+    clippy::upper_case_acronyms
+)]
+mod dylib_types {
+    use std::ffi::c_uint;
+
+    use crate::sys::libcuviddec_sys::CUcontext;
+
+    pub type NV_OF_STATUS = c_uint;
+
+    pub const NV_OF_SUCCESS: NV_OF_STATUS = 0;
+    pub const NV_OF_ERR_OF_NOT_AVAILABLE: NV_OF_STATUS = 1;
+    pub const NV_OF_ERR_UNSUPPORTED_DEVICE: NV_OF_STATUS = 2;
+
+    pub type NV_OF_CAPS = c_uint;
+
+    pub const NV_OF_CAPS_SUPPORTED_OUTPUT_GRID_SIZES: NV_OF_CAPS = 0;
+    pub const NV_OF_CAPS_SUPPORTED_HINT_GRID_SIZES: NV_OF_CAPS = 1;
+    pub const NV_OF_CAPS_SUPPORT_HINT_WITH_OF_MODE: NV_OF_CAPS = 2;
+    pub const NV_OF_CAPS_SUPPORT_HINT_WITH_ST_MODE: NV_OF_CAPS = 3;
+    pub const NV_OF_CAPS_WIDTH_MIN: NV_OF_CAPS = 4;
+    pub const NV_OF_CAPS_HEIGHT_MIN: NV_OF_CAPS = 5;
+    pub const NV_OF_CAPS_WIDTH_MAX: NV_OF_CAPS = 6;
+    pub const NV_OF_CAPS_HEIGHT_MAX: NV_OF_CAPS = 7;
+    pub const NV_OF_CAPS_SUPPORT_ROI: NV_OF_CAPS = 8;
+    pub const NV_OF_CAPS_SUPPORT_ROI_MAX_NUM: NV_OF_CAPS = 9;
+
+    #[repr(C)]
+    pub struct NvOFHandle_st {
+        _unused: [u8; 0],
+    }
+
+    pub type NvOFHandle = *mut NvOFHandle_st;
+
+    pub type PFNNVCREATEOPTICALFLOWCUDA =
+        Option<unsafe extern "C" fn(CUcontext, *mut NvOFHandle) -> NV_OF_STATUS>;
+    pub type PFNNVOFDESTROY = Option<unsafe extern "C" fn(NvOFHandle) -> NV_OF_STATUS>;
+    pub type PFNNVOFGETCAPS =
+        Option<unsafe extern "C" fn(NvOFHandle, NV_OF_CAPS, *mut u32, *mut u32) -> NV_OF_STATUS>;
+    pub type PFNNVOFUNUSED = Option<unsafe extern "C" fn()>;
+
+    #[repr(C)]
+    pub struct NV_OF_CUDA_API_FUNCTION_LIST {
+        pub nvCreateOpticalFlowCuda: PFNNVCREATEOPTICALFLOWCUDA,
+        pub nvOFInit: PFNNVOFUNUSED,
+        pub nvOFCreateGPUBufferCuda: PFNNVOFUNUSED,
+        pub nvOFGPUBufferGetCUarray: PFNNVOFUNUSED,
+        pub nvOFGPUBufferGetCUdeviceptr: PFNNVOFUNUSED,
+        pub nvOFGPUBufferGetStrideInfo: PFNNVOFUNUSED,
+        pub nvOFSetIOCudaStreams: PFNNVOFUNUSED,
+        pub nvOFExecute: PFNNVOFUNUSED,
+        pub nvOFDestroyGPUBufferCuda: PFNNVOFUNUSED,
+        pub nvOFDestroy: PFNNVOFDESTROY,
+        pub nvOFGetLastError: PFNNVOFUNUSED,
+        pub nvOFGetCaps: PFNNVOFGETCAPS,
+        /// Room for the functions later API versions append.
+        pub reserved: [PFNNVOFUNUSED; 32],
+    }
+
+    pub type NvOFAPICreateInstanceCuda =
+        unsafe extern "C" fn(u32, *mut NV_OF_CUDA_API_FUNCTION_LIST) -> NV_OF_STATUS;
+    pub type NvOFGetMaxSupportedApiVersion = unsafe extern "C" fn(*mut u32) -> NV_OF_STATUS;
+}
+
+/// The Optical Flow API version requested from the driver, encoded as `(major << 4) | minor`.
+/// 2.0 is the first version supported by every GPU with an optical flow accelerator.
+const NV_OF_API_VERSION: u32 = 2 << 4;
+
+macro_rules! call_of_fn {
+    ($optical_flow: expr, $func: ident ($($arg: expr),*)) => {{
+        match $optical_flow.api.$func {
+            Some(func) => {
+                let status = unsafe { func($optical_flow.handle, $($arg),*) };
+                if status != NV_OF_SUCCESS {
+                    return Err(NvidiaError::OpticalFlowFailed(status));
+                }
+            }
+            None => return Err(NvidiaError::SymbolNotFound(stringify!($func))),
+        }
+    }};
+}
+
+/// Queries the capabilities of the optical flow accelerator of the device `context` was created
+/// on. Returns `None` if `libnvidia-opticalflow.so.1` isn't installed, the driver is too old or
+/// the device doesn't have an optical flow accelerator (GPUs before Turing).
+pub fn get_optical_flow_caps(context: CUcontext) -> Result<Option<OpticalFlowCaps>, NvidiaError> {
+    let Some(optical_flow) = NvOpticalFlow::new(context)? else {
+        return Ok(None);
+    };
+
+    let flag = |caps: NV_OF_CAPS| -> Result<bool, NvidiaError> {
+        Ok(optical_flow
+            .get_caps(caps)?
+            .first()
+            .is_some_and(|it| *it != 0))
+    };
+    let value = |caps: NV_OF_CAPS| -> Result<u32, NvidiaError> {
+        Ok(optical_flow
+            .get_caps(caps)?
+            .first()
+            .copied()
+            .unwrap_or_default())
+    };
+
+    let roi_supported = flag(NV_OF_CAPS_SUPPORT_ROI)?;
+
+    Ok(Some(OpticalFlowCaps {
+        output_grid_sizes: optical_flow.get_caps(NV_OF_CAPS_SUPPORTED_OUTPUT_GRID_SIZES)?,
+        hint_grid_sizes: optical_flow.get_caps(NV_OF_CAPS_SUPPORTED_HINT_GRID_SIZES)?,
+        hint_with_optical_flow: flag(NV_OF_CAPS_SUPPORT_HINT_WITH_OF_MODE)?,
+        hint_with_stereo_disparity: flag(NV_OF_CAPS_SUPPORT_HINT_WITH_ST_MODE)?,
+        min_width: value(NV_OF_CAPS_WIDTH_MIN)?,
+        min_height: value(NV_OF_CAPS_HEIGHT_MIN)?,
+        max_width: value(NV_OF_CAPS_WIDTH_MAX)?,
+        max_height: value(NV_OF_CAPS_HEIGHT_MAX)?,
+        roi_supported,
+        max_roi_count: if roi_supported {
+            value(NV_OF_CAPS_SUPPORT_ROI_MAX_NUM)?
+        } else {
+            0
+        },
+    }))
+}
+
+/// An optical flow session that is only used to query capabilities, it's never initialized for
+/// actual work.
+struct NvOpticalFlow {
+    api: NV_OF_CUDA_API_FUNCTION_LIST,
+    handle: NvOFHandle,
+}
+
+impl Drop for NvOpticalFlow {
+    fn drop(&mut self) {
+        if let Some(nv_of_destroy) = self.api.nvOFDestroy {
+            unsafe {
+                nv_of_destroy(self.handle);
+            }
+        }
+    }
+}
+
+impl NvOpticalFlow {
+    fn new(context: CUcontext) -> Result<Option<NvOpticalFlow>, NvidiaError> {
+        let lib_optical_flow = match *LIBNV_OPTICAL_FLOW {
+            Ok(lib) => lib,
+            Err(err) => {
+                log_debug!("Optical flow library not available: {}", err);
+                return Ok(None);
+            }
+        };
+
+        if let Some(sym_get_max_version) =
+            get_sym_opt!(lib_optical_flow, NvOFGetMaxSupportedApiVersion)
+        {
+            let mut max_version = 0u32;
+            let status = unsafe { sym_get_max_version(&mut max_version) };
+            if status == NV_OF_SUCCESS && max_version < NV_OF_API_VERSION {
+                log_debug!(
+                    "Optical flow API {}.{} not supported by the driver",
+                    NV_OF_API_VERSION >> 4,
+                    NV_OF_API_VERSION & 0xf
+                );
+                return Ok(None);
+            }
+        }
+
+        let sym_create_instance = get_sym!(lib_optical_flow, NvOFAPICreateInstanceCuda);
+
+        let mut api: NV_OF_CUDA_API_FUNCTION_LIST = unsafe { zeroed() };
+        let status = unsafe { sym_create_instance(NV_OF_API_VERSION, &mut api) };
+        if status != NV_OF_SUCCESS {
+            return Err(NvidiaError::OpticalFlowFailed(status));
+        }
+
+        let Some(create_optical_flow) = api.nvCreateOpticalFlowCuda else {
+            return Err(NvidiaError::SymbolNotFound(stringify!(
+                nvCreateOpticalFlowCuda
+            )));
+        };
+
+        let mut handle: NvOFHandle = ptr::null_mut();
+        match unsafe { create_optical_flow(context, &mut handle) } {
+            NV_OF_SUCCESS => Ok(Some(NvOpticalFlow { api, handle })),
+            NV_OF_ERR_OF_NOT_AVAILABLE | NV_OF_ERR_UNSUPPORTED_DEVICE => Ok(None),
+            status => Err(NvidiaError::OpticalFlowFailed(status)),
+        }
+    }
+
+    /// Returns the values of `caps`, the first call only determines their count.
+    fn get_caps(&self, caps: NV_OF_CAPS) -> Result<Vec<u32>, NvidiaError> {
+        let mut count = 0u32;
+        call_of_fn!(self, nvOFGetCaps(caps, ptr::null_mut(), &mut count));
+
+        let mut values = vec![0u32; count as usize];
+        if count > 0 {
+            call_of_fn!(self, nvOFGetCaps(caps, values.as_mut_ptr(), &mut count));
+        }
+
+        values.truncate(count as usize);
+
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::context::CudaContext;
+    use crate::device::enumerate_devices;
+    use crate::dylib::is_cuda_loaded;
+
+    use super::*;
+
+    #[test]
+    fn test_get_optical_flow_caps() -> Result<(), Box<dyn Error>> {
+        if !is_cuda_loaded() {
+            eprintln!("libcuda.so not available");
+            return Ok(());
+        }
+
+        let devices = enumerate_devices()?;
+        assert!(!devices.is_empty());
+
+        let context = CudaContext::new(devices.first().unwrap())?;
+        let caps = context.with_floating_ctx(|context| get_optical_flow_caps(*context))?;
+        dbg!(&caps);
+
+        if let Some(caps) = caps {
+            assert!(!caps.output_grid_sizes.is_empty());
+            assert!(caps.max_width >= caps.min_width);
+            assert!(caps.max_height >= caps.min_height);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * Minimal stand-in for libnvidia-ml.so used by the NVML tests. It exposes a single device with
+ * fixed telemetry values, see nvidia/src/nvml.rs.
+ */
+
+#include <string.h>
+
+typedef unsigned int nvmlReturn_t;
+typedef struct nvmlDevice_st *nvmlDevice_t;
+
+#define NVML_SUCCESS 0
+#define NVML_ERROR_INVALID_ARGUMENT 2
+#define NVML_ERROR_NOT_FOUND 6
+#define NVML_ERROR_INSUFFICIENT_SIZE 7
+
+typedef struct {
+    unsigned int gpu;
+    unsigned int memory;
+} nvmlUtilization_t;
+
+typedef struct {
+    unsigned long long total;
+    unsigned long long free;
+    unsigned long long used;
+} nvmlMemory_t;
+
+typedef struct {
+    unsigned int sessionId;
+    unsigned int pid;
+    unsigned int vgpuInstance;
+    unsigned int codecType;
+    unsigned int hResolution;
+    unsigned int vResolution;
+    unsigned int averageFps;
+    unsigned int averageLatency;
+} nvmlEncoderSessionInfo_t;
+
+static int device;
+
+#define STUB_DEVICE ((nvmlDevice_t) &device)
+
+nvmlReturn_t nvmlInit_v2(void) {
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetHandleByUUID(const char *uuid, nvmlDevice_t *handle) {
+    if (strcmp(uuid, "GPU-4a8e7f0c-1d2b-4c3a-9e5f-6a7b8c9d0e1f") != 0) {
+        return NVML_ERROR_NOT_FOUND;
+    }
+
+    *handle = STUB_DEVICE;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetHandleByPciBusId_v2(const char *pciBusId, nvmlDevice_t *handle) {
+    if (strcmp(pciBusId, "0000:65:00.0") != 0) {
+        return NVML_ERROR_NOT_FOUND;
+    }
+
+    *handle = STUB_DEVICE;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetUtilizationRates(nvmlDevice_t handle, nvmlUtilization_t *utilization) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    utilization->gpu = 42;
+    utilization->memory = 10;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetEncoderUtilization(
+    nvmlDevice_t handle,
+    unsigned int *utilization,
+    unsigned int *samplingPeriodUs
+) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    *utilization = 17;
+    *samplingPeriodUs = 1000000;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetDecoderUtilization(
+    nvmlDevice_t handle,
+    unsigned int *utilization,
+    unsigned int *samplingPeriodUs
+) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    *utilization = 23;
+    *samplingPeriodUs = 1000000;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetEncoderSessions(
+    nvmlDevice_t handle,
+    unsigned int *sessionCount,
+    nvmlEncoderSessionInfo_t *sessionInfos
+) {
+    static const nvmlEncoderSessionInfo_t sessions[] = {
+        {1, 1000, 0, 0, 1920, 1080, 30, 1000},
+        {2, 1001, 0, 1, 3840, 2160, 60, 3000},
+    };
+
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    if (*sessionCount == 0) {
+        *sessionCount = 2;
+        return NVML_SUCCESS;
+    }
+
+    if (*sessionCount < 2) {
+        *sessionCount = 2;
+        return NVML_ERROR_INSUFFICIENT_SIZE;
+    }
+
+    memcpy(sessionInfos, sessions, sizeof(sessions));
+    *sessionCount = 2;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetTemperature(
+    nvmlDevice_t handle,
+    unsigned int sensorType,
+    unsigned int *temp
+) {
+    if (handle != STUB_DEVICE || sensorType != 0) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    *temp = 55;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetPowerUsage(nvmlDevice_t handle, unsigned int *power) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    *power = 120000;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetMemoryInfo(nvmlDevice_t handle, nvmlMemory_t *memory) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    memory->total = 8ULL << 30;
+    memory->used = 1ULL << 30;
+    memory->free = memory->total - memory->used;
+    return NVML_SUCCESS;
+}
+
+nvmlReturn_t nvmlDeviceGetIndex(nvmlDevice_t handle, unsigned int *index) {
+    if (handle != STUB_DEVICE) {
+        return NVML_ERROR_INVALID_ARGUMENT;
+    }
+
+    *index = 3;
+    return NVML_SUCCESS;
+}
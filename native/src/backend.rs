@@ -0,0 +1,341 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::error::Error;
+use std::panic::resume_unwind;
+use std::path::PathBuf;
+use std::thread;
+
+use common::owned::{Device, Diagnostic, LibraryInfo, ScanOptions, ScanResult};
+use common::{log_debug, log_error, Driver, ErrorCode};
+
+use crate::last_error::set_last_error;
+#[cfg(feature = "nvidia")]
+use crate::nvidia::NvidiaBackend;
+#[cfg(feature = "vaapi")]
+use crate::vaapi::VaapiBackend;
+
+/// All known backends in order of priority. Backends listed first are scanned first, so their
+/// devices take precedence when [DedupHints] are evaluated by later backends.
+///
+/// Each backend is only compiled in if its cargo feature is enabled.
+pub(crate) static BACKENDS: &[&dyn DynHwBackend] = &[
+    #[cfg(feature = "nvidia")]
+    &NvidiaBackend,
+    #[cfg(feature = "vaapi")]
+    &VaapiBackend,
+];
+
+/// A source of hardware encoding and decoding devices, e.g. a driver API like NVENC/NVDEC or
+/// VA-API.
+pub trait HwBackend {
+    /// Identifies a single device before it is probed, e.g. a CUDA device or a DRM path.
+    type Handle: Sync;
+    type Error: Error + Send + Sync + 'static;
+
+    /// Short name used in diagnostics, e.g. `nvidia`.
+    fn name(&self) -> &'static str;
+
+    /// The driver reported for all devices of this backend.
+    fn driver(&self) -> Driver;
+
+    /// Returns whether the libraries required by this backend are present on the system.
+    /// Unavailable backends are skipped without an error.
+    fn is_available(&self) -> bool;
+
+    /// Reports the libraries this backend tried to load and where they were loaded from, so
+    /// unavailable backends can be diagnosed.
+    fn libraries(&self) -> Vec<LibraryInfo> {
+        Vec::new()
+    }
+
+    fn enumerate(&self) -> Result<Vec<Self::Handle>, Self::Error>;
+
+    /// Queries the capabilities of a single device, restricted to the codecs selected in
+    /// `options`.
+    ///
+    /// Every device is probed on its own thread, concurrently with the other devices of the
+    /// backend, so any state shared between probes must be synchronized.
+    ///
+    /// Returns `None` if the device should be skipped, e.g. because `hints` show that the same
+    /// hardware was already reported by a higher priority backend.
+    fn probe(
+        &self,
+        handle: &Self::Handle,
+        options: &ScanOptions,
+        hints: &DedupHints,
+    ) -> Result<Option<Device>, Self::Error>;
+
+    /// Identifies `handle` in diagnostics.
+    fn device_id(&self, handle: &Self::Handle) -> DeviceId;
+
+    /// Classifies a backend specific error for the C interface.
+    fn error_code(&self, error: &Self::Error) -> ErrorCode;
+
+    /// Extracts the status code returned by the underlying driver API, if there is one.
+    fn native_code(&self, error: &Self::Error) -> Option<i64>;
+}
+
+/// Identifies a device in a [Diagnostic], matching the fields of [Device].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeviceId {
+    Ordinal(u8),
+    Path(PathBuf),
+}
+
+impl DeviceId {
+    /// Returns whether the device was selected for probing in `options`.
+    pub fn is_included(&self, options: &ScanOptions) -> bool {
+        match self {
+            DeviceId::Ordinal(ordinal) => options.includes_cuda_ordinal(*ordinal),
+            DeviceId::Path(path) => options.includes_drm_path(path),
+        }
+    }
+}
+
+/// Object safe variant of [HwBackend] so backends with different handle and error types can be
+/// registered together.
+pub trait DynHwBackend: Sync {
+    fn name(&self) -> &'static str;
+
+    fn driver(&self) -> Driver;
+
+    fn is_available(&self) -> bool;
+
+    /// Enumerates and probes all devices of this backend selected in `options`, returns `None` if
+    /// the backend is disabled. The result of an unavailable backend only contains the libraries
+    /// it tried to load.
+    ///
+    /// Failures don't abort the scan, they are reported as [Diagnostic]s alongside all devices
+    /// that could be probed successfully. Devices are probed in parallel, but reported in the order
+    /// they were enumerated in.
+    fn scan(&self, options: &ScanOptions, hints: &DedupHints) -> Option<ScanResult>;
+}
+
+impl<B> DynHwBackend for B
+where
+    B: HwBackend + Sync,
+{
+    fn name(&self) -> &'static str {
+        HwBackend::name(self)
+    }
+
+    fn driver(&self) -> Driver {
+        HwBackend::driver(self)
+    }
+
+    fn is_available(&self) -> bool {
+        HwBackend::is_available(self)
+    }
+
+    fn scan(&self, options: &ScanOptions, hints: &DedupHints) -> Option<ScanResult> {
+        if !options.is_driver_enabled(HwBackend::driver(self)) {
+            log_debug!("{} backend disabled, skipping", HwBackend::name(self));
+            return None;
+        }
+
+        let mut result = ScanResult {
+            libraries: self.libraries(),
+            ..ScanResult::default()
+        };
+
+        if !self.is_available() {
+            log_debug!("{} backend not available, skipping", HwBackend::name(self));
+            return Some(result);
+        }
+
+        let handles = match self.enumerate() {
+            Ok(handles) => handles,
+            Err(err) => {
+                result.diagnostics.push(diagnose(self, None, &err));
+                return Some(result);
+            }
+        };
+
+        let handles = handles
+            .iter()
+            .filter(|handle| self.device_id(handle).is_included(options))
+            .collect::<Vec<_>>();
+
+        // diagnostics are created on the calling thread, as the last error is thread local
+        let probe_results = thread::scope(|scope| {
+            let probes = handles
+                .iter()
+                .map(|&handle| {
+                    scope.spawn(move || {
+                        log_debug!(
+                            "Probing {} device {:?}",
+                            HwBackend::name(self),
+                            self.device_id(handle)
+                        );
+
+                        self.probe(handle, options, hints)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            probes
+                .into_iter()
+                .map(|probe| probe.join().unwrap_or_else(|err| resume_unwind(err)))
+                .collect::<Vec<_>>()
+        });
+
+        for (handle, probe_result) in handles.into_iter().zip(probe_results) {
+            match probe_result {
+                Ok(Some(device)) => result.devices.push(device),
+                Ok(None) => {}
+                Err(err) => result.diagnostics.push(diagnose(self, Some(handle), &err)),
+            }
+        }
+
+        Some(result)
+    }
+}
+
+fn diagnose<B: HwBackend>(backend: &B, handle: Option<&B::Handle>, error: &B::Error) -> Diagnostic {
+    let device_id = handle.map(|it| backend.device_id(it));
+
+    let native_code = backend.native_code(error);
+    let message = error.to_string();
+
+    log_error!("{} backend failed: {}", backend.name(), message);
+    set_last_error(Some(backend.name()), native_code, message.clone());
+
+    Diagnostic {
+        driver: backend.driver(),
+        ordinal: match device_id {
+            Some(DeviceId::Ordinal(ordinal)) => Some(ordinal),
+            _ => None,
+        },
+        path: match device_id {
+            Some(DeviceId::Path(path)) => Some(path),
+            _ => None,
+        },
+        kind: backend.error_code(error),
+        native_code,
+        message,
+    }
+}
+
+/// Keeps track of the drivers that were already scanned successfully.
+///
+/// Some drivers expose the same hardware through a compatibility layer (e.g. NVDEC through the
+/// VA-API `nvidia-vaapi-driver`), which would otherwise be reported twice.
+#[derive(Debug, Default)]
+pub struct DedupHints {
+    scanned_drivers: Vec<Driver>,
+}
+
+impl DedupHints {
+    pub fn is_scanned(&self, driver: Driver) -> bool {
+        self.scanned_drivers.contains(&driver)
+    }
+
+    pub(crate) fn mark_scanned(&mut self, driver: Driver) {
+        self.scanned_drivers.push(driver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Probes devices `0..4`, later devices finish first and device `2` fails.
+    #[derive(Default)]
+    struct SlowBackend {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl HwBackend for SlowBackend {
+        type Handle = u8;
+        type Error = io::Error;
+
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn driver(&self) -> Driver {
+            Driver::Nvidia
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn enumerate(&self) -> Result<Vec<u8>, io::Error> {
+            Ok(vec![0, 1, 2, 3])
+        }
+
+        fn probe(
+            &self,
+            handle: &u8,
+            _: &ScanOptions,
+            _: &DedupHints,
+        ) -> Result<Option<Device>, io::Error> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+
+            thread::sleep(Duration::from_millis(50 * u64::from(4 - handle)));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            match handle {
+                2 => Err(io::Error::other("device 2 failed")),
+                _ => Ok(Some(Device::new_with_ordinal(
+                    Driver::Nvidia,
+                    *handle,
+                    format!("Device {}", handle),
+                    vec![],
+                ))),
+            }
+        }
+
+        fn device_id(&self, handle: &u8) -> DeviceId {
+            DeviceId::Ordinal(*handle)
+        }
+
+        fn error_code(&self, _: &io::Error) -> ErrorCode {
+            ErrorCode::OperationFailed
+        }
+
+        fn native_code(&self, _: &io::Error) -> Option<i64> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_scan_probes_in_parallel() {
+        let backend = SlowBackend::default();
+        let result =
+            DynHwBackend::scan(&backend, &ScanOptions::default(), &DedupHints::default()).unwrap();
+
+        assert!(backend.max_running.load(Ordering::SeqCst) > 1);
+
+        let ordinals = result
+            .devices
+            .iter()
+            .map(|it| it.ordinal.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ordinals, [0, 1, 3]);
+
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].ordinal, Some(2));
+    }
+}
@@ -0,0 +1,357 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Command-line front end running the same scan as `scan_devices`.
+//!
+//! Prints a table of all devices and their capabilities, or the JSON document described in the
+//! README with `--json`. Exits with status `1` if no device was found.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use enc_dec_hwscan::log::{set_max_log_level, LogLevel};
+use enc_dec_hwscan::owned::{ScanOptions, ScanResult};
+use enc_dec_hwscan::{json, scan_with_options, Codec, EncoderStatus, ThreeValue};
+
+const USAGE: &str = "\
+Usage: enc-dec-hwscan [OPTIONS]
+
+Scans the system for hardware encoding and decoding devices.
+
+Options:
+  --json              Print the result as JSON
+  --pretty            Pretty-print the JSON output
+  --no-nvidia         Don't scan NVIDIA devices
+  --no-vaapi          Don't scan VA-API devices
+  --ordinal <N>       Only scan the CUDA device with ordinal N, may be repeated
+  --device <ID>       Only scan the NVIDIA device whose nvidia-smi index (not CUDA ordinal), UUID
+                      or PCI bus ID is ID, may be repeated
+  --path <PATH>       Only scan the DRM device at PATH, may be repeated
+  --codec <CODEC>     Only probe CODEC (mpeg1, mpeg2, mpeg4, vc1, h264, hevc, vp8, vp9, av1),
+                      may be repeated
+  --probe-sessions    Probe how many NVENC sessions can be opened concurrently
+  --primary-context   Probe NVIDIA devices with their primary CUDA context
+  --nvidia-lib-dir <DIR>
+                      Search DIR for the NVIDIA libraries first, may be repeated
+  -v, --verbose       Log debug messages to stderr
+  -h, --help          Print this help
+";
+
+const EXIT_NO_DEVICES: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_FAILURE: u8 = 3;
+
+struct Args {
+    json: bool,
+    pretty: bool,
+    verbose: bool,
+    options: ScanOptions,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("enc-dec-hwscan: {}\n\n{}", err, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    if args.verbose {
+        set_max_log_level(LogLevel::Debug);
+    }
+
+    let result = scan_with_options(&args.options);
+
+    if args.json {
+        let json = if args.pretty {
+            json::to_json_pretty(&result)
+        } else {
+            json::to_json(&result)
+        };
+
+        match json {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                eprintln!("enc-dec-hwscan: failed to serialize result: {}", err);
+                return ExitCode::from(EXIT_FAILURE);
+            }
+        }
+    } else {
+        print_table(&result);
+        print_diagnostics(&result);
+
+        if args.verbose || result.devices.is_empty() {
+            print_libraries(&result);
+        }
+    }
+
+    if result.devices.is_empty() {
+        ExitCode::from(EXIT_NO_DEVICES)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut result = Args {
+        json: false,
+        pretty: false,
+        verbose: false,
+        options: ScanOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => result.json = true,
+            "--pretty" => result.pretty = true,
+            "--no-nvidia" => result.options.nvidia = false,
+            "--no-vaapi" => result.options.vaapi = false,
+            "--probe-sessions" => result.options.probe_encoder_sessions = true,
+            "--primary-context" => result.options.use_primary_context = true,
+            "--ordinal" => {
+                let value = args.next().ok_or("missing value for --ordinal")?;
+                let ordinal = value
+                    .parse()
+                    .map_err(|_| format!("invalid ordinal: {}", value))?;
+
+                result
+                    .options
+                    .cuda_ordinals
+                    .get_or_insert_with(Vec::new)
+                    .push(ordinal);
+            }
+            "--device" => {
+                let value = args.next().ok_or("missing value for --device")?;
+
+                result
+                    .options
+                    .nvidia_device_mask
+                    .get_or_insert_with(Vec::new)
+                    .push(value);
+            }
+            "--path" => {
+                let value = args.next().ok_or("missing value for --path")?;
+
+                result
+                    .options
+                    .drm_paths
+                    .get_or_insert_with(Vec::new)
+                    .push(PathBuf::from(value));
+            }
+            "--nvidia-lib-dir" => {
+                let value = args.next().ok_or("missing value for --nvidia-lib-dir")?;
+
+                result
+                    .options
+                    .nvidia_library_dirs
+                    .push(PathBuf::from(value));
+            }
+            "--codec" => {
+                let value = args.next().ok_or("missing value for --codec")?;
+                let codec = parse_codec(&value).ok_or(format!("unknown codec: {}", value))?;
+
+                result
+                    .options
+                    .codecs
+                    .get_or_insert_with(Vec::new)
+                    .push(codec);
+            }
+            "-v" | "--verbose" => result.verbose = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+
+    Ok(Some(result))
+}
+
+fn parse_codec(value: &str) -> Option<Codec> {
+    match value.to_lowercase().as_str() {
+        "mpeg1" => Some(Codec::Mpeg1),
+        "mpeg2" => Some(Codec::Mpeg2),
+        "mpeg4" => Some(Codec::Mpeg4),
+        "vc1" => Some(Codec::Vc1),
+        "h264" | "avc" => Some(Codec::H264),
+        "hevc" | "h265" => Some(Codec::Hevc),
+        "vp8" => Some(Codec::Vp8),
+        "vp9" => Some(Codec::Vp9),
+        "av1" => Some(Codec::Av1),
+        _ => None,
+    }
+}
+
+fn print_table(result: &ScanResult) {
+    const HEADER: [&str; 8] = [
+        "DEVICE", "CODEC", "MODE", "CHROMA", "DEPTH", "PROFILE", "MAX SIZE", "B-FRAMES",
+    ];
+
+    let mut rows: Vec<[String; 8]> = Vec::new();
+
+    for device in result.devices.iter() {
+        let mut device_name = format!(
+            "{:?} {} ({})",
+            device.driver,
+            device
+                .ordinal
+                .map(|it| it.to_string())
+                .or_else(|| device.path.as_ref().map(|it| it.display().to_string()))
+                .unwrap_or_default(),
+            device.name.as_deref().unwrap_or("unknown"),
+        );
+
+        if let Some(cuda) = &device.cuda {
+            device_name.push_str(&format!(" {}", cuda.pci_address));
+
+            if let Some(nvml_index) = cuda.nvml_index {
+                device_name.push_str(&format!(" GPU {}", nvml_index));
+            }
+        }
+
+        if let Some(nvenc_api_version) = device.nvenc_api_version {
+            device_name.push_str(&format!(" NVENC {}", nvenc_api_version));
+        }
+
+        if let Some(max_concurrent_sessions) = device.max_concurrent_sessions {
+            device_name.push_str(&format!(" ({} sessions)", max_concurrent_sessions));
+        }
+
+        match device.encoder_status {
+            EncoderStatus::Available => {}
+            EncoderStatus::Cached => device_name.push_str(" [busy, cached]"),
+            EncoderStatus::Busy => device_name.push_str(" [busy]"),
+            EncoderStatus::UnsupportedApi => device_name.push_str(" [NVENC API unsupported]"),
+        }
+
+        for codec_details in device.codecs.iter() {
+            for spec in codec_details.decoding_specs.iter() {
+                rows.push([
+                    device_name.clone(),
+                    format!("{:?}", codec_details.codec),
+                    "decode".to_string(),
+                    format!("{:?}", spec.chroma),
+                    format!("{:?}", spec.color_depth),
+                    "-".to_string(),
+                    format!("{}x{}", spec.max_width, spec.max_height),
+                    "-".to_string(),
+                ]);
+            }
+
+            for spec in codec_details.encoding_specs.iter() {
+                rows.push([
+                    device_name.clone(),
+                    format!("{:?}", codec_details.codec),
+                    "encode".to_string(),
+                    format!("{:?}", spec.chroma),
+                    format!("{:?}", spec.color_depth),
+                    format!("{:?}", spec.profile),
+                    format!("{}x{}", spec.max_width, spec.max_height),
+                    match spec.b_frames_supported {
+                        ThreeValue::True => "yes",
+                        ThreeValue::False => "no",
+                        ThreeValue::Unknown => "unknown",
+                    }
+                    .to_string(),
+                ]);
+            }
+        }
+
+        if let Some(optical_flow) = &device.optical_flow {
+            rows.push([
+                device_name.clone(),
+                "-".to_string(),
+                "optical flow".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                format!("{}x{}", optical_flow.max_width, optical_flow.max_height),
+                "-".to_string(),
+            ]);
+        }
+    }
+
+    if rows.is_empty() {
+        println!("No devices found");
+        return;
+    }
+
+    let mut widths = HEADER.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    print_row(&HEADER, &widths);
+    for row in rows.iter() {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row<S: AsRef<str>>(cells: &[S; 8], widths: &[usize; 8]) {
+    let line = cells
+        .iter()
+        .zip(widths.iter())
+        .map(|(cell, width)| format!("{:<width$}", cell.as_ref(), width = width))
+        .collect::<Vec<_>>()
+        .join("  ");
+
+    println!("{}", line.trim_end());
+}
+
+fn print_libraries(result: &ScanResult) {
+    for library in result.libraries.iter() {
+        match (&library.path, &library.error) {
+            (Some(path), _) => eprintln!(
+                "{:?}: loaded {} from {}",
+                library.driver,
+                library.name,
+                path.display()
+            ),
+            (None, error) => eprintln!(
+                "{:?}: failed to load {}: {}",
+                library.driver,
+                library.name,
+                error.as_deref().unwrap_or("unknown error")
+            ),
+        }
+    }
+}
+
+fn print_diagnostics(result: &ScanResult) {
+    for diagnostic in result.diagnostics.iter() {
+        let device = diagnostic
+            .ordinal
+            .map(|it| format!(" {}", it))
+            .or_else(|| {
+                diagnostic
+                    .path
+                    .as_ref()
+                    .map(|it| format!(" {}", it.display()))
+            })
+            .unwrap_or_default();
+
+        eprintln!(
+            "{:?}{}: {:?}: {}",
+            diagnostic.driver, device, diagnostic.kind, diagnostic.message
+        );
+    }
+}
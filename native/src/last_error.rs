@@ -0,0 +1,126 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+/// Details of the most recent error that occurred on the current thread.
+#[derive(Debug)]
+struct LastError {
+    backend: Option<CString>,
+    native_code: Option<i64>,
+    message: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn set_last_error(
+    backend: Option<&'static str>,
+    native_code: Option<i64>,
+    message: impl Into<String>,
+) {
+    let last_error = LastError {
+        backend: backend.and_then(|it| CString::new(it).ok()),
+        native_code,
+        message: CString::new(message.into().replace('\0', "")).unwrap_or_default(),
+    };
+
+    LAST_ERROR.with_borrow_mut(|it| *it = Some(last_error));
+}
+
+pub(crate) fn clear_last_error() {
+    LAST_ERROR.with_borrow_mut(|it| *it = None);
+}
+
+/// Returns the message of the most recent error that occurred on the calling thread, or `NULL` if
+/// there is none.
+///
+/// The returned string is owned by the library and stays valid until the next call to
+/// `scan_devices` on the same thread.
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub extern "C" fn get_last_error_message() -> *const c_char {
+    LAST_ERROR.with_borrow(|last_error| match last_error {
+        Some(last_error) => last_error.message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Returns the status code the driver API returned for the most recent error that occurred on the
+/// calling thread, e.g. a `CUresult`, `NVENCSTATUS` or `VAStatus`. Returns `0` if there is no
+/// error or it didn't originate from a driver call.
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub extern "C" fn get_last_error_native_code() -> i64 {
+    LAST_ERROR.with_borrow(|last_error| {
+        last_error
+            .as_ref()
+            .and_then(|it| it.native_code)
+            .unwrap_or_default()
+    })
+}
+
+/// Returns the name of the backend (`nvidia` or `vaapi`) in which the most recent error on the
+/// calling thread occurred, or `NULL` if there is no error or it didn't occur in a backend.
+///
+/// The returned string is owned by the library and stays valid until the next call to
+/// `scan_devices` on the same thread.
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub extern "C" fn get_last_error_backend() -> *const c_char {
+    LAST_ERROR.with_borrow(|last_error| {
+        match last_error.as_ref().and_then(|it| it.backend.as_ref()) {
+            Some(backend) => backend.as_ptr(),
+            None => ptr::null(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_last_error() {
+        clear_last_error();
+
+        assert!(get_last_error_message().is_null());
+        assert!(get_last_error_backend().is_null());
+        assert_eq!(get_last_error_native_code(), 0);
+
+        set_last_error(Some("nvidia"), Some(2), "Operation failed: 2");
+
+        unsafe {
+            assert_eq!(
+                CStr::from_ptr(get_last_error_message()).to_str(),
+                Ok("Operation failed: 2")
+            );
+            assert_eq!(
+                CStr::from_ptr(get_last_error_backend()).to_str(),
+                Ok("nvidia")
+            );
+        }
+        assert_eq!(get_last_error_native_code(), 2);
+    }
+}
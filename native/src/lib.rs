@@ -14,20 +14,100 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use std::panic::catch_unwind;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, UnwindSafe};
+use std::ptr;
 
 pub use common::*;
-use ::nvidia::NvidiaError;
-use ::vaapi::VaError;
 
-use crate::error::ErrorCode;
-use crate::nvidia::get_nvidia_devices;
-use crate::vaapi::get_vaapi_devices;
+use crate::backend::BACKENDS;
+pub use crate::backend::{DedupHints, DeviceId, DynHwBackend, HwBackend};
+use crate::last_error::{clear_last_error, set_last_error};
+pub use crate::last_error::{
+    get_last_error_backend, get_last_error_message, get_last_error_native_code,
+};
+pub use crate::log_callback::{set_log_callback, set_log_level, LogCallback};
 
-mod error;
+mod backend;
+mod last_error;
+mod log_callback;
+#[cfg(feature = "nvidia")]
 mod nvidia;
+#[cfg(feature = "vaapi")]
 mod vaapi;
 
+#[cfg(feature = "nvidia")]
+use crate::nvidia::query_telemetry;
+#[cfg(feature = "nvidia")]
+use ::nvidia::add_library_search_dirs;
+
+#[cfg(not(feature = "nvidia"))]
+fn query_telemetry(_: &owned::CudaDeviceInfo) -> Option<owned::Telemetry> {
+    None
+}
+
+#[cfg(not(feature = "nvidia"))]
+fn add_library_search_dirs(_: &[std::path::PathBuf]) {}
+
+/// Scans all registered backends for hardware encoding and decoding capabilities.
+///
+/// Backends whose libraries aren't available on this system are skipped silently. Devices or
+/// backends that fail to scan don't abort the scan but are reported in
+/// [owned::ScanResult::diagnostics]. The last of these failures is also available through
+/// [get_last_error_message] and its siblings.
+pub fn scan() -> owned::ScanResult {
+    scan_with_options(&owned::ScanOptions::default())
+}
+
+/// Same as [scan], but only probes the backends, devices and codecs selected in `options`.
+pub fn scan_with_options(options: &owned::ScanOptions) -> owned::ScanResult {
+    scan_backends(options).0
+}
+
+/// Scans like [scan_with_options] and additionally returns whether any backend succeeded, i.e. it
+/// was available and didn't fail without reporting a single device.
+fn scan_backends(options: &owned::ScanOptions) -> (owned::ScanResult, bool) {
+    clear_last_error();
+
+    add_library_search_dirs(&options.nvidia_library_dirs);
+
+    let mut hints = DedupHints::default();
+    let mut result = owned::ScanResult::default();
+    let mut succeeded = false;
+
+    for backend in BACKENDS {
+        if let Some(backend_result) = backend.scan(options, &hints) {
+            if !backend_result.devices.is_empty() {
+                hints.mark_scanned(backend.driver());
+            }
+
+            succeeded |= backend_succeeded(backend.is_available(), &backend_result);
+
+            result.devices.extend(backend_result.devices);
+            result.diagnostics.extend(backend_result.diagnostics);
+            result.libraries.extend(backend_result.libraries);
+        }
+    }
+
+    (result, succeeded)
+}
+
+/// A backend failed if it reported diagnostics but not a single device, an unavailable backend
+/// neither failed nor succeeded.
+fn backend_succeeded(available: bool, result: &owned::ScanResult) -> bool {
+    available && (!result.devices.is_empty() || result.diagnostics.is_empty())
+}
+
+/// Re-queries the runtime telemetry of `devices` without scanning their capabilities again.
+///
+/// Only devices using CUDA report telemetry, and only if NVML is available. The telemetry of all
+/// other devices is left empty.
+pub fn refresh_telemetry(devices: &mut [owned::Device]) {
+    for device in devices.iter_mut() {
+        device.telemetry = device.cuda.as_ref().and_then(query_telemetry);
+    }
+}
+
 /// # Safety
 ///
 /// Part of the public C interface.
@@ -41,71 +121,167 @@ pub unsafe extern "C" fn free_devices(ptr: *mut EncDecDevices) {
 /// Part of the public C interface.
 #[no_mangle]
 pub unsafe extern "C" fn scan_devices(result: *mut *mut EncDecDevices) -> ErrorCode {
-    catch_unwind(|| {
-        let (nvidia_devices, nvidia_done) = match get_nvidia_devices() {
-            Ok(devs) => (devs, true),
-            Err(err) => {
-                if let NvidiaError::NotLoaded(_) = err {
-                    (vec![], false)
-                } else {
-                    eprintln!("enc-dec-hwscan error: {}", err);
-                    return map_nvidia_error_code(err);
-                }
+    scan_devices_with_options(ptr::null(), result)
+}
+
+/// Same as `scan_devices`, but only probes the backends, devices and codecs selected in
+/// `options`. Passing `NULL` scans everything.
+///
+/// Failures of single devices or backends are reported in the `diagnostics` of the result. Only if
+/// no backend succeeded and at least one failed, the scan fails with the kind of the last failure
+/// and `result` isn't set, the failure is available through `get_last_error_message` and its
+/// siblings. Without any available backend the result is empty.
+///
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn scan_devices_with_options(
+    options: *const ScanOptions,
+    result: *mut *mut EncDecDevices,
+) -> ErrorCode {
+    catch_critical_error(|| {
+        let options = if options.is_null() {
+            owned::ScanOptions::default()
+        } else {
+            ScanOptions::as_owned(options)
+        };
+        let (scan_result, succeeded) = scan_backends(&options);
+        if !succeeded {
+            // the last failure is also the last error
+            if let Some(diagnostic) = scan_result.diagnostics.last() {
+                return diagnostic.kind;
+            }
+        }
+
+        // make sure this is done last and only if errno is going to be 0
+        *result = Box::into_raw(Box::new(EncDecDevices::from(scan_result)));
+        ErrorCode::Success
+    })
+}
+
+/// Re-queries the runtime telemetry of all devices in `devices` in place, without scanning their
+/// capabilities again. Pointers to the previous telemetry of a device become invalid.
+///
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn refresh_devices_telemetry(devices: *mut EncDecDevices) -> ErrorCode {
+    catch_critical_error(|| {
+        if let Some(devices) = devices.as_mut() {
+            for device in devices.devices_mut() {
+                let telemetry = device
+                    .cuda()
+                    .map(|&it| owned::CudaDeviceInfo::from(it))
+                    .as_ref()
+                    .and_then(query_telemetry);
+
+                device.set_telemetry(telemetry);
             }
+        }
+
+        ErrorCode::Success
+    })
+}
+
+/// Queries the current runtime telemetry of the CUDA device described by `cuda` and writes it to
+/// `out`. Fails with `DriverFailure` if NVML isn't available or doesn't know the device.
+///
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn get_device_telemetry(
+    cuda: *const CudaDeviceInfo,
+    out: *mut Telemetry,
+) -> ErrorCode {
+    catch_critical_error(|| {
+        clear_last_error();
+
+        let Some(cuda) = cuda.as_ref() else {
+            set_last_error(None, None, "No CUDA device specified");
+            return ErrorCode::OperationFailed;
         };
 
-        let vaapi_devices = match get_vaapi_devices(nvidia_done) {
-            Ok(devs) => devs,
+        match query_telemetry(&owned::CudaDeviceInfo::from(*cuda)) {
+            Some(telemetry) => {
+                *out = Telemetry::from(telemetry);
+                ErrorCode::Success
+            }
+            None => {
+                set_last_error(
+                    Some("nvidia"),
+                    None,
+                    format!("No telemetry available for {}", cuda.pci_address),
+                );
+                ErrorCode::DriverFailure
+            }
+        }
+    })
+}
+
+/// Scans all devices like `scan_devices` and writes the result to `out` as a nul-terminated JSON
+/// document, see the README for its schema. The string must be released with `free_string`.
+///
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn scan_devices_json(out: *mut *mut c_char) -> ErrorCode {
+    catch_critical_error(|| {
+        let json = match json::to_json(&scan()) {
+            Ok(json) => json,
             Err(err) => {
-                if let VaError::NotLoaded(_) = err {
-                    vec![]
-                } else {
-                    eprintln!("enc-dec-hwscan error: {}", err);
-                    return map_vaapi_error_code(err);
-                }
+                log_error!("Failed to serialize scan result: {}", err);
+                set_last_error(None, None, err.to_string());
+
+                return ErrorCode::ConversionFailed;
             }
         };
 
-        let all_devices = nvidia_devices.into_iter().chain(vaapi_devices).collect();
-
-        // make sure this is done last and only if errno is going to be 0
-        *result = Box::into_raw(Box::new(EncDecDevices::new(all_devices)));
-        ErrorCode::Success
-    })
-    .unwrap_or_else(|err| {
-        eprintln!(
-            "Critical error in enc_dec_hwscan::detect_devices: {:?}",
-            err
-        );
-        ErrorCode::CriticalError
+        match CString::new(json) {
+            Ok(json) => {
+                *out = json.into_raw();
+                ErrorCode::Success
+            }
+            Err(err) => {
+                set_last_error(None, None, err.to_string());
+                ErrorCode::ConversionFailed
+            }
+        }
     })
 }
 
-fn map_nvidia_error_code(error: NvidiaError) -> ErrorCode {
-    match error {
-        NvidiaError::NotLoaded(_) => ErrorCode::Success,
-        NvidiaError::OperationFailed(_) => ErrorCode::OperationFailed,
-        NvidiaError::SymbolNotFound(_) => ErrorCode::DriverFailure,
-        NvidiaError::NvEncFunctionNotAvailable(_) => ErrorCode::DriverFailure,
-        NvidiaError::FailedToConvertUuid(_) => ErrorCode::ConversionFailed,
-        NvidiaError::FailedToConvertResult(_) => ErrorCode::ConversionFailed,
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        let _ = CString::from_raw(ptr);
     }
 }
 
-fn map_vaapi_error_code(error: VaError) -> ErrorCode {
-    match error {
-        VaError::NotLoaded(_) => ErrorCode::Success,
-        VaError::SymbolNotFound(_) => ErrorCode::DriverFailure,
-        VaError::FailedToEnumerateDevices(_) => ErrorCode::DriverFailure,
-        VaError::FailedToOpenDevice(_, _) => ErrorCode::DriverFailure,
-        VaError::FailedToGetDisplay(_) => ErrorCode::DriverFailure,
-        VaError::OperationFailed(_, _) => ErrorCode::OperationFailed,
-    }
+fn catch_critical_error(f: impl FnOnce() -> ErrorCode + UnwindSafe) -> ErrorCode {
+    catch_unwind(f).unwrap_or_else(|err| {
+        let message = err
+            .downcast_ref::<&str>()
+            .map(|it| it.to_string())
+            .or_else(|| err.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_string());
+
+        log_error!("Critical error in enc_dec_hwscan: {}", message);
+        set_last_error(None, None, message);
+
+        ErrorCode::CriticalError
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use std::ptr;
+    use std::ffi::CStr;
+    use std::mem::{offset_of, size_of};
 
     use super::*;
 
@@ -121,4 +297,156 @@ mod tests {
             free_devices(target);
         }
     }
+
+    #[test]
+    fn test_scan() {
+        let result = scan();
+
+        dbg!(&result);
+    }
+
+    #[test]
+    fn test_scan_devices_json() {
+        unsafe {
+            let mut target = ptr::null_mut::<c_char>();
+
+            assert_eq!(ErrorCode::Success, scan_devices_json(&mut target));
+
+            let json = CStr::from_ptr(target).to_str().unwrap();
+            assert!(json.starts_with(&format!("{{\"schemaVersion\":{}", json::SCHEMA_VERSION)));
+
+            // devices carry live telemetry, so the round trip is checked against the same scan
+            let result = json::from_json(json).unwrap();
+            assert_eq!(json::to_json(&result).unwrap(), json);
+
+            free_string(target);
+        }
+    }
+
+    #[test]
+    fn test_scan_with_options() {
+        let codecs = [Codec::Hevc, Codec::Av1];
+        let options = ScanOptions {
+            size: size_of::<ScanOptions>() as u32,
+            disable_nvidia: false,
+            disable_vaapi: true,
+            cuda_ordinals: ptr::null(),
+            num_cuda_ordinals: 0,
+            drm_paths: ptr::null(),
+            num_drm_paths: 0,
+            codecs: codecs.as_ptr(),
+            num_codecs: codecs.len() as u32,
+            nvidia_library_dirs: ptr::null(),
+            num_nvidia_library_dirs: 0,
+            probe_encoder_sessions: false,
+            nvidia_device_mask: ptr::null(),
+            num_nvidia_device_mask: 0,
+            use_primary_context: false,
+        };
+
+        unsafe {
+            let mut target = ptr::null_mut::<EncDecDevices>();
+
+            assert_eq!(
+                ErrorCode::Success,
+                scan_devices_with_options(&options, &mut target)
+            );
+
+            free_devices(target);
+        }
+
+        let result = scan_with_options(&unsafe { ScanOptions::as_owned(&options) });
+        for device in result.devices {
+            assert_eq!(device.driver, Driver::Nvidia);
+            assert!(device
+                .codecs
+                .iter()
+                .all(|it| codecs.contains(&it.codec)));
+        }
+    }
+
+    #[test]
+    fn test_backend_succeeded() {
+        let diagnostic = owned::Diagnostic {
+            driver: Driver::Nvidia,
+            ordinal: Some(0),
+            path: None,
+            kind: ErrorCode::InitFailed,
+            native_code: Some(999),
+            message: "Failed to initialize CUDA".to_string(),
+        };
+        let device =
+            owned::Device::new_with_ordinal(Driver::Nvidia, 1, "Device 1".to_string(), vec![]);
+
+        let empty = owned::ScanResult::default();
+        assert!(backend_succeeded(true, &empty));
+        assert!(!backend_succeeded(false, &empty));
+
+        let failed = owned::ScanResult {
+            diagnostics: vec![diagnostic.clone()],
+            ..owned::ScanResult::default()
+        };
+        assert!(!backend_succeeded(true, &failed));
+
+        let partial = owned::ScanResult {
+            devices: vec![device],
+            diagnostics: vec![diagnostic],
+            ..owned::ScanResult::default()
+        };
+        assert!(backend_succeeded(true, &partial));
+    }
+
+    #[test]
+    fn test_scan_options_size() {
+        let codecs = [Codec::Hevc];
+        let mut options = ScanOptions {
+            size: size_of::<ScanOptions>() as u32,
+            disable_nvidia: true,
+            disable_vaapi: false,
+            cuda_ordinals: ptr::null(),
+            num_cuda_ordinals: 0,
+            drm_paths: ptr::null(),
+            num_drm_paths: 0,
+            codecs: codecs.as_ptr(),
+            num_codecs: codecs.len() as u32,
+            nvidia_library_dirs: ptr::null(),
+            num_nvidia_library_dirs: 0,
+            probe_encoder_sessions: true,
+            nvidia_device_mask: ptr::null(),
+            num_nvidia_device_mask: 0,
+            use_primary_context: true,
+        };
+
+        let owned = unsafe { ScanOptions::as_owned(&options) };
+        assert!(!owned.nvidia);
+        assert_eq!(owned.codecs, Some(vec![Codec::Hevc]));
+        assert!(owned.probe_encoder_sessions);
+        assert!(owned.use_primary_context);
+
+        // fields must not be placed in the padding of an older layout
+        assert!(offset_of!(ScanOptions, probe_encoder_sessions) >= 56);
+        assert!(offset_of!(ScanOptions, use_primary_context) >= 72);
+
+        // a caller built against the header that ended with `probe_encoder_sessions`
+        options.size = 72;
+
+        let owned = unsafe { ScanOptions::as_owned(&options) };
+        assert!(owned.probe_encoder_sessions);
+        assert!(!owned.use_primary_context);
+
+        // a caller built against the first header, which ended with `num_codecs`
+        options.size = 56;
+
+        let owned = unsafe { ScanOptions::as_owned(&options) };
+        assert!(!owned.nvidia);
+        assert_eq!(owned.codecs, Some(vec![Codec::Hevc]));
+        assert!(!owned.probe_encoder_sessions);
+        assert!(!owned.use_primary_context);
+
+        options.size = 0;
+        assert_eq!(
+            unsafe { ScanOptions::as_owned(&options) },
+            owned::ScanOptions::default()
+        );
+    }
 }
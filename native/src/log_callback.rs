@@ -0,0 +1,113 @@
+/*
+ * Copyright (C) 2024 Alex Katlein <dev@vemilyus.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::ffi::{c_char, CString};
+
+use common::log::{reset_log_sink, set_log_sink, set_max_log_level, LogLevel};
+
+/// Receives a log message with its level and target, e.g. `nvidia::caps::encode`.
+///
+/// Both strings are only valid for the duration of the call. The callback may be invoked from any
+/// thread that calls into the library, and concurrently from the worker threads a scan probes its
+/// devices on. It must therefore be thread-safe, and it must not block waiting for the thread that
+/// started the scan, which waits for its workers to finish.
+pub type LogCallback =
+    Option<unsafe extern "C" fn(level: LogLevel, target: *const c_char, message: *const c_char)>;
+
+/// Routes all log messages to `callback`, passing `NULL` restores logging to stderr.
+///
+/// # Safety
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub unsafe extern "C" fn set_log_callback(callback: LogCallback) {
+    match callback {
+        Some(callback) => set_log_sink(move |level, target: &str, message: &str| {
+            let target = CString::new(target.replace('\0', "")).unwrap_or_default();
+            let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+
+            unsafe { callback(level, target.as_ptr(), message.as_ptr()) }
+        }),
+        None => reset_log_sink(),
+    }
+}
+
+/// Sets the most verbose level that is still logged, defaults to `Info`.
+///
+/// Part of the public C interface.
+#[no_mangle]
+pub extern "C" fn set_log_level(level: LogLevel) {
+    set_max_log_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::sync::Mutex;
+
+    use common::log_warn;
+
+    use super::*;
+
+    static MESSAGES: Mutex<Vec<(LogLevel, String, String)>> = Mutex::new(Vec::new());
+    // the sink is global, tests replacing it must not run concurrently
+    static SINK_LOCK: Mutex<()> = Mutex::new(());
+
+    unsafe extern "C" fn callback(level: LogLevel, target: *const c_char, message: *const c_char) {
+        let target = CStr::from_ptr(target).to_string_lossy().into_owned();
+        let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+
+        MESSAGES.lock().unwrap().push((level, target, message));
+    }
+
+    unsafe extern "C" fn resetting_callback(
+        level: LogLevel,
+        target: *const c_char,
+        message: *const c_char,
+    ) {
+        set_log_callback(None);
+        callback(level, target, message);
+    }
+
+    #[test]
+    fn test_set_log_callback() {
+        let _guard = SINK_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        unsafe { set_log_callback(Some(callback)) };
+        log_warn!("Unknown codec GUID: {}", 42);
+        unsafe { set_log_callback(None) };
+
+        assert!(MESSAGES.lock().unwrap().contains(&(
+            LogLevel::Warn,
+            "enc_dec_hwscan::log_callback::tests".to_string(),
+            "Unknown codec GUID: 42".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_set_log_callback_from_callback() {
+        let _guard = SINK_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        unsafe { set_log_callback(Some(resetting_callback)) };
+        log_warn!("Replacing the sink from within itself");
+
+        assert!(MESSAGES.lock().unwrap().contains(&(
+            LogLevel::Warn,
+            "enc_dec_hwscan::log_callback::tests".to_string(),
+            "Replacing the sink from within itself".to_string()
+        )));
+    }
+}
@@ -14,14 +14,23 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 
-use common::{Codec, CodecDetails, Device, Driver};
+use common::owned::{CodecDetails, CudaDeviceInfo, Device, LibraryInfo, ScanOptions, Telemetry};
+use common::{log_debug, log_warn, Codec, Driver, EncoderStatus, ErrorCode, PciAddress};
 use nvidia::caps::{get_decode_capabilities, get_encode_capabilities, CudaDecodeSpec};
-use nvidia::context::CudaContext;
-use nvidia::device::enumerate_devices;
-use nvidia::encoder::NvEncoder;
-use nvidia::NvidiaError;
+use nvidia::context::{is_device_unavailable, CudaContext};
+use nvidia::device::{enumerate_devices, get_device_info, CudaDevice};
+use nvidia::encoder::{
+    is_session_limit_reached, max_supported_version, negotiate_api_version, probe_max_sessions,
+    NvEncoder,
+};
+use nvidia::nvml::nvml;
+use nvidia::optical_flow::get_optical_flow_caps;
+use nvidia::{InitFailure, NvidiaError};
+
+use crate::backend::{DedupHints, DeviceId, HwBackend};
 
 const ALL_CODECS: [Codec; 9] = [
     Codec::Mpeg1,
@@ -35,32 +44,173 @@ const ALL_CODECS: [Codec; 9] = [
     Codec::Av1,
 ];
 
-pub fn get_nvidia_devices() -> Result<Vec<Device>, NvidiaError> {
-    let mut result = Vec::new();
+/// Capabilities of a device from its last successful scan, keyed by codec.
+#[derive(Clone, Debug, Default)]
+struct CachedCaps {
+    decode: BTreeMap<Codec, CodecDetails>,
+    encode: BTreeMap<Codec, CodecDetails>,
+}
+
+/// Capabilities of every device scanned successfully by this process, used while a device is busy.
+static CAPS_CACHE: Mutex<BTreeMap<PciAddress, CachedCaps>> = Mutex::new(BTreeMap::new());
+
+pub struct NvidiaBackend;
+
+impl HwBackend for NvidiaBackend {
+    type Handle = CudaDevice;
+    type Error = NvidiaError;
+
+    fn name(&self) -> &'static str {
+        "nvidia"
+    }
+
+    fn driver(&self) -> Driver {
+        Driver::Nvidia
+    }
+
+    fn is_available(&self) -> bool {
+        nvidia::is_available()
+    }
+
+    fn libraries(&self) -> Vec<LibraryInfo> {
+        nvidia::libraries()
+            .into_iter()
+            .map(|(name, library)| LibraryInfo {
+                driver: Driver::Nvidia,
+                name: name.to_string(),
+                path: library.ok().map(|it| it.to_path_buf()),
+                error: library.err().map(|it| it.to_string()),
+            })
+            .collect()
+    }
+
+    fn enumerate(&self) -> Result<Vec<CudaDevice>, NvidiaError> {
+        enumerate_devices()
+    }
+
+    fn probe(
+        &self,
+        device: &CudaDevice,
+        options: &ScanOptions,
+        _: &DedupHints,
+    ) -> Result<Option<Device>, NvidiaError> {
+        let device_info = get_device_info(device)?;
+        if !options.includes_nvidia_device(&device_info) {
+            return Ok(None);
+        }
 
-    let devices = enumerate_devices()?;
+        let ctx = if options.use_primary_context {
+            CudaContext::retain_primary(device)
+        } else {
+            CudaContext::new(device)
+        };
+        let ctx = match ctx {
+            Ok(ctx) => Some(ctx),
+            Err(err) if is_device_unavailable(&err) => {
+                log_warn!(
+                    "{} is in use by another process: {}",
+                    device_info.pci_address,
+                    err
+                );
+                None
+            }
+            Err(err) => return Err(err),
+        };
 
-    for device in devices {
-        let ctx = CudaContext::new(&device)?;
+        let decode_specs = match &options.codecs {
+            Some(codecs) => CudaDecodeSpec::for_codecs(codecs),
+            None => CudaDecodeSpec::all(),
+        };
 
         let mut decode_caps: HashMap<Codec, CodecDetails> = HashMap::new();
         let mut encode_caps: HashMap<Codec, CodecDetails> = HashMap::new();
+        let mut busy = ctx.is_none();
+        let mut api_unsupported = false;
 
-        ctx.with_ctx(|| get_decode_capabilities(&CudaDecodeSpec::all()))?
-            .into_iter()
-            .for_each(|codec_details| {
-                decode_caps.insert(codec_details.codec(), codec_details);
+        if let Some(ctx) = &ctx {
+            ctx.with_ctx(|| get_decode_capabilities(&decode_specs, device_info.driver_version))?
+                .into_iter()
+                .for_each(|codec_details| {
+                    decode_caps.insert(codec_details.codec, codec_details);
+                });
+
+            let encode_result = ctx.with_floating_ctx(|context| {
+                let encoder = NvEncoder::new(context)?;
+                get_encode_capabilities(&encoder, options.codecs.as_deref())
             });
 
-        ctx.with_floating_ctx(|context| {
-            let encoder = NvEncoder::new(context)?;
+            match encode_result {
+                Ok(encode_codec_details) => {
+                    encode_codec_details.into_iter().for_each(|codec_details| {
+                        encode_caps.insert(codec_details.codec, codec_details);
+                    });
+                }
+                Err(err) if is_session_limit_reached(&err) => {
+                    log_warn!(
+                        "All encode sessions of {} are in use: {}",
+                        device_info.pci_address,
+                        err
+                    );
+                    busy = true;
+                }
+                Err(NvidiaError::UnsupportedApiVersion(driver_api_version)) => {
+                    log_warn!(
+                        "Skipping encoding capabilities of {}, driver only supports NVENC API {}",
+                        device_info.pci_address,
+                        driver_api_version
+                    );
+                    api_unsupported = true;
+                }
+                Err(err) => return Err(err),
+            }
+        }
 
-            get_encode_capabilities(&encoder)
-        })?
-        .into_iter()
-        .for_each(|codec_details| {
-            encode_caps.insert(codec_details.codec(), codec_details);
-        });
+        let encoder_status = if api_unsupported {
+            EncoderStatus::UnsupportedApi
+        } else if busy {
+            complete_from_cache(
+                device_info.pci_address,
+                ctx.is_some(),
+                &mut decode_caps,
+                &mut encode_caps,
+                options,
+            )
+        } else {
+            update_cached_caps(device_info.pci_address, &decode_caps, &encode_caps);
+            EncoderStatus::Available
+        };
+
+        let max_concurrent_sessions = match &ctx {
+            Some(ctx) if options.probe_encoder_sessions && !api_unsupported => {
+                match ctx.with_floating_ctx(|context| probe_max_sessions(context)) {
+                    Ok(max_sessions) => Some(max_sessions),
+                    Err(err) => {
+                        log_warn!(
+                            "Failed to probe encoder sessions of {}: {}",
+                            device_info.pci_address,
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let optical_flow = match &ctx {
+            Some(ctx) => match ctx.with_floating_ctx(|context| get_optical_flow_caps(*context)) {
+                Ok(optical_flow) => optical_flow,
+                Err(err) => {
+                    log_warn!(
+                        "Failed to probe optical flow accelerator of {}: {}",
+                        device_info.pci_address,
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
 
         let codec_details = ALL_CODECS
             .iter()
@@ -76,13 +226,254 @@ pub fn get_nvidia_devices() -> Result<Vec<Device>, NvidiaError> {
             })
             .collect::<Vec<_>>();
 
-        result.push(Device::new_with_ordinal(
+        let mut result = Device::new_with_ordinal(
             Driver::Nvidia,
-            device.handle as u8,
-            device.name,
+            device.ordinal as u8,
+            device.name.clone(),
             codec_details,
-        ))
+        );
+        // the negotiated version doesn't depend on an encode session, so it's known even if the
+        // device is busy
+        result.nvenc_api_version = match max_supported_version().and_then(negotiate_api_version) {
+            Ok(nvenc_api_version) => Some(nvenc_api_version),
+            Err(NvidiaError::UnsupportedApiVersion(_)) => None,
+            Err(err) => {
+                log_warn!(
+                    "Failed to query the NVENC API version of {}: {}",
+                    device_info.pci_address,
+                    err
+                );
+                None
+            }
+        };
+        result.max_concurrent_sessions = max_concurrent_sessions;
+        result.encoder_status = encoder_status;
+        result.optical_flow = optical_flow;
+        result.telemetry = query_telemetry(&device_info);
+        result.cuda = Some(device_info);
+
+        Ok(Some(result))
     }
 
-    Ok(result)
+    fn device_id(&self, device: &CudaDevice) -> DeviceId {
+        DeviceId::Ordinal(device.ordinal as u8)
+    }
+
+    fn error_code(&self, error: &NvidiaError) -> ErrorCode {
+        match error {
+            NvidiaError::NotLoaded(_) => ErrorCode::DriverFailure,
+            NvidiaError::OperationFailed(_) => ErrorCode::OperationFailed,
+            NvidiaError::SymbolNotFound(_) => ErrorCode::DriverFailure,
+            NvidiaError::NvEncFunctionNotAvailable(_) => ErrorCode::DriverFailure,
+            NvidiaError::FailedToConvertUuid(_) => ErrorCode::ConversionFailed,
+            NvidiaError::FailedToConvertResult(_) => ErrorCode::ConversionFailed,
+            NvidiaError::FailedToParsePciBusId(_) => ErrorCode::ConversionFailed,
+            NvidiaError::NvmlFailed(_) => ErrorCode::NvmlFailed,
+            NvidiaError::OpticalFlowFailed(_) => ErrorCode::OpticalFlowFailed,
+            NvidiaError::InitFailed(failure, _) => match failure {
+                InitFailure::NoDevice => ErrorCode::NoDevice,
+                InitFailure::InsufficientDriver => ErrorCode::InsufficientDriver,
+                InitFailure::DriverMismatch => ErrorCode::DriverMismatch,
+                InitFailure::DeviceUnavailable => ErrorCode::DeviceUnavailable,
+                InitFailure::Other => ErrorCode::InitFailed,
+            },
+            NvidiaError::SessionLimit(_) => ErrorCode::OperationFailed,
+            NvidiaError::UnsupportedApiVersion(_) => ErrorCode::InsufficientDriver,
+        }
+    }
+
+    fn native_code(&self, error: &NvidiaError) -> Option<i64> {
+        match error {
+            NvidiaError::OperationFailed(status) => Some((*status).into()),
+            NvidiaError::InitFailed(_, status) => Some((*status).into()),
+            NvidiaError::SessionLimit(status) => Some((*status).into()),
+            NvidiaError::NvmlFailed(status) => Some((*status).into()),
+            NvidiaError::OpticalFlowFailed(status) => Some((*status).into()),
+            _ => None,
+        }
+    }
+}
+
+fn cached_caps(pci_address: &PciAddress) -> Option<CachedCaps> {
+    CAPS_CACHE.lock().unwrap().get(pci_address).cloned()
+}
+
+fn update_cached_caps(
+    pci_address: PciAddress,
+    decode_caps: &HashMap<Codec, CodecDetails>,
+    encode_caps: &HashMap<Codec, CodecDetails>,
+) {
+    let mut cache = CAPS_CACHE.lock().unwrap();
+    let cached = cache.entry(pci_address).or_default();
+
+    cached.decode.extend(decode_caps.clone());
+    cached.encode.extend(encode_caps.clone());
+}
+
+/// Completes the capabilities of a busy device from [CAPS_CACHE]. If `decode_probed` the decoding
+/// capabilities were probed despite the busy encoder, so they're cached and not replaced.
+fn complete_from_cache(
+    pci_address: PciAddress,
+    decode_probed: bool,
+    decode_caps: &mut HashMap<Codec, CodecDetails>,
+    encode_caps: &mut HashMap<Codec, CodecDetails>,
+    options: &ScanOptions,
+) -> EncoderStatus {
+    if decode_probed {
+        update_cached_caps(pci_address, decode_caps, &HashMap::new());
+    }
+
+    let cached = cached_caps(&pci_address).unwrap_or_default();
+    if !decode_probed {
+        decode_caps.extend(filter_codecs(cached.decode, options));
+    }
+
+    if cached.encode.is_empty() {
+        EncoderStatus::Busy
+    } else {
+        encode_caps.extend(filter_codecs(cached.encode, options));
+        EncoderStatus::Cached
+    }
+}
+
+fn filter_codecs(
+    caps: BTreeMap<Codec, CodecDetails>,
+    options: &ScanOptions,
+) -> impl Iterator<Item = (Codec, CodecDetails)> + '_ {
+    caps.into_iter()
+        .filter(|(codec, _)| options.includes_codec(*codec))
+}
+
+/// Queries the current telemetry of a CUDA device through NVML. Returns `None` if NVML isn't
+/// available or fails to query the device.
+pub fn query_telemetry(cuda: &CudaDeviceInfo) -> Option<Telemetry> {
+    let nvml = nvml()?;
+
+    match nvml.telemetry(cuda.uuid, cuda.pci_address) {
+        Ok(telemetry) => Some(telemetry),
+        Err(err) => {
+            log_debug!("Failed to query telemetry of {}: {}", cuda.pci_address, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::ApiVersion;
+
+    use super::*;
+
+    #[test]
+    fn test_cached_caps() {
+        let pci_address = "0000:ff:1f.7".parse().unwrap();
+        let busy_pci_address = "0000:fe:1f.7".parse().unwrap();
+        assert!(cached_caps(&pci_address).is_none());
+
+        let decode_caps =
+            HashMap::from([(Codec::Hevc, CodecDetails::new(Codec::Hevc, vec![], vec![]))]);
+        let encode_caps =
+            HashMap::from([(Codec::Av1, CodecDetails::new(Codec::Av1, vec![], vec![]))]);
+
+        // a later scan only adds to the cached capabilities
+        update_cached_caps(pci_address, &decode_caps, &HashMap::new());
+        update_cached_caps(pci_address, &HashMap::new(), &encode_caps);
+
+        let cached = cached_caps(&pci_address).unwrap();
+        assert_eq!(cached.decode.keys().collect::<Vec<_>>(), [&Codec::Hevc]);
+        assert_eq!(cached.encode.keys().collect::<Vec<_>>(), [&Codec::Av1]);
+
+        let options = ScanOptions {
+            codecs: Some(vec![Codec::Hevc]),
+            ..ScanOptions::default()
+        };
+        assert_eq!(filter_codecs(cached.decode, &options).count(), 1);
+        assert_eq!(filter_codecs(cached.encode, &options).count(), 0);
+
+        // the first scan of a busy device only probed the decoding capabilities, which are
+        // cached as well
+        let all = ScanOptions::default();
+        let mut busy_decode_caps = decode_caps.clone();
+        let mut busy_encode_caps = HashMap::new();
+        let status = complete_from_cache(
+            busy_pci_address,
+            true,
+            &mut busy_decode_caps,
+            &mut busy_encode_caps,
+            &all,
+        );
+        assert_eq!(status, EncoderStatus::Busy);
+        assert!(busy_encode_caps.is_empty());
+
+        // the next scan couldn't create a context at all
+        let mut busy_decode_caps = HashMap::new();
+        let status = complete_from_cache(
+            busy_pci_address,
+            false,
+            &mut busy_decode_caps,
+            &mut busy_encode_caps,
+            &all,
+        );
+        assert_eq!(status, EncoderStatus::Busy);
+        assert_eq!(busy_decode_caps.keys().collect::<Vec<_>>(), [&Codec::Hevc]);
+
+        // once the encoder was probed, its capabilities are served from the cache
+        update_cached_caps(busy_pci_address, &HashMap::new(), &encode_caps);
+
+        let mut busy_decode_caps = HashMap::new();
+        let status = complete_from_cache(
+            busy_pci_address,
+            false,
+            &mut busy_decode_caps,
+            &mut busy_encode_caps,
+            &all,
+        );
+        assert_eq!(status, EncoderStatus::Cached);
+        assert_eq!(busy_decode_caps.keys().collect::<Vec<_>>(), [&Codec::Hevc]);
+        assert_eq!(busy_encode_caps.keys().collect::<Vec<_>>(), [&Codec::Av1]);
+    }
+
+    #[test]
+    fn test_device_mask() {
+        let cuda = CudaDeviceInfo {
+            uuid: Some("4a8e7f0c-1d2b-4c3a-9e5f-6a7b8c9d0e1f".parse().unwrap()),
+            pci_address: "0000:65:00.0".parse().unwrap(),
+            compute_capability: ApiVersion::new(8, 6),
+            total_memory: 8 << 30,
+            multiprocessor_count: 28,
+            integrated: false,
+            tcc_driver: false,
+            driver_version: ApiVersion::new(12, 4),
+            nvml_index: Some(3),
+        };
+
+        let includes_device = |mask: &[&str], cuda: &CudaDeviceInfo| {
+            let options = ScanOptions {
+                nvidia_device_mask: Some(mask.iter().map(|it| it.to_string()).collect()),
+                ..ScanOptions::default()
+            };
+
+            options.includes_nvidia_device(cuda)
+        };
+        let includes = |mask: &[&str]| includes_device(mask, &cuda);
+
+        assert!(ScanOptions::default().includes_nvidia_device(&cuda));
+        assert!(includes(&["3"]));
+        assert!(includes(&["0", "GPU-4A8E7F0C"]));
+        assert!(includes(&["4a8e7f0c-1d2b-4c3a-9e5f-6a7b8c9d0e1f"]));
+        assert!(includes(&["00000000:65:00.0"]));
+        assert!(!includes(&[]));
+        assert!(!includes(&["0", "GPU-", "0000:66:00.0", "GPU-4a8e7f0d"]));
+
+        // Integers are NVML indices: a device that CUDA_VISIBLE_DEVICES=3 turns into ordinal 0
+        // is still selected by "3" only, and without NVML only UUIDs and PCI bus IDs match.
+        let without_nvml = CudaDeviceInfo {
+            nvml_index: None,
+            ..cuda
+        };
+        assert!(!includes_device(&["0"], &without_nvml));
+        assert!(!includes_device(&["3"], &without_nvml));
+        assert!(includes_device(&["GPU-4a8e", "3"], &without_nvml));
+        assert!(includes_device(&["0000:65:00.0"], &without_nvml));
+    }
 }
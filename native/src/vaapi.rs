@@ -14,32 +14,81 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
-use common::{Device, Driver};
+use std::path::PathBuf;
+
+use common::owned::{Device, ScanOptions};
+use common::{Driver, ErrorCode};
 use vaapi::caps::get_capabilities;
 use vaapi::device::enumerate_devices;
 use vaapi::display::DrmDisplay;
 use vaapi::VaError;
 
-pub fn get_vaapi_devices(nvidia_done: bool) -> Result<Vec<Device>, VaError> {
-    let mut result = Vec::new();
+use crate::backend::{DedupHints, DeviceId, HwBackend};
+
+pub struct VaapiBackend;
 
-    let devices = enumerate_devices()?;
+impl HwBackend for VaapiBackend {
+    type Handle = PathBuf;
+    type Error = VaError;
 
-    for device in devices {
-        let display = DrmDisplay::new(&device)?;
-        if nvidia_done && display.vendor.to_lowercase().contains("nvdec") {
-            continue;
+    fn name(&self) -> &'static str {
+        "vaapi"
+    }
+
+    fn driver(&self) -> Driver {
+        Driver::Vaapi
+    }
+
+    fn is_available(&self) -> bool {
+        vaapi::is_va_loaded()
+    }
+
+    fn enumerate(&self) -> Result<Vec<PathBuf>, VaError> {
+        enumerate_devices()
+    }
+
+    fn probe(
+        &self,
+        device: &PathBuf,
+        options: &ScanOptions,
+        hints: &DedupHints,
+    ) -> Result<Option<Device>, VaError> {
+        let display = DrmDisplay::new(device)?;
+        if hints.is_scanned(Driver::Nvidia) && display.vendor.to_lowercase().contains("nvdec") {
+            return Ok(None);
         }
 
-        let codec_details = get_capabilities(&display)?;
+        let codec_details = get_capabilities(&display, options.codecs.as_deref())?;
 
-        result.push(Device::new_with_path(
+        Ok(Some(Device::new_with_path(
             Driver::Vaapi,
-            device.to_string_lossy().to_string(),
+            device.clone(),
             Some(display.vendor.clone()),
             codec_details,
-        ))
+        )))
+    }
+
+    fn device_id(&self, device: &PathBuf) -> DeviceId {
+        DeviceId::Path(device.clone())
+    }
+
+    fn error_code(&self, error: &VaError) -> ErrorCode {
+        match error {
+            VaError::NotLoaded(_) => ErrorCode::DriverFailure,
+            VaError::SymbolNotFound(_) => ErrorCode::DriverFailure,
+            VaError::FailedToEnumerateDevices(_) => ErrorCode::DriverFailure,
+            VaError::FailedToOpenDevice(_, _) => ErrorCode::DriverFailure,
+            VaError::FailedToGetDisplay(_) => ErrorCode::DriverFailure,
+            VaError::OperationFailed(_, _) => ErrorCode::OperationFailed,
+        }
     }
 
-    Ok(result)
+    fn native_code(&self, error: &VaError) -> Option<i64> {
+        match error {
+            VaError::OperationFailed(_, status) => Some((*status).into()),
+            VaError::FailedToEnumerateDevices(err) => err.raw_os_error().map(i64::from),
+            VaError::FailedToOpenDevice(_, err) => err.raw_os_error().map(i64::from),
+            _ => None,
+        }
+    }
 }
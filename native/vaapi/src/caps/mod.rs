@@ -16,8 +16,9 @@
  */
 use std::collections::HashMap;
 
+use common::owned::CodecDetails;
 use common::{
-    Chroma, Codec, CodecDetails, ColorDepth, DecodingSpec, EncodeProfile, EncodingSpec, ThreeValue,
+    Chroma, Codec, ColorDepth, DecodingSpec, EncodeProfile, EncodingSpec, OutputFormats, ThreeValue,
 };
 
 use crate::display::DrmDisplay;
@@ -48,12 +49,19 @@ const ALL_CODECS: [Codec; 9] = [
     Codec::Av1,
 ];
 
-pub fn get_capabilities(display: &DrmDisplay) -> Result<Vec<CodecDetails>, VaError> {
+/// Queries the capabilities of all codecs supported by `display`, or only of `codecs` if specified.
+pub fn get_capabilities(
+    display: &DrmDisplay,
+    codecs: Option<&[Codec]>,
+) -> Result<Vec<CodecDetails>, VaError> {
     let mut decode_caps: HashMap<Codec, Vec<DecodingSpec>> = HashMap::new();
     let mut encode_caps: HashMap<Codec, Vec<EncodingSpec>> = HashMap::new();
 
     for profile in display.query_profiles()? {
-        let profile_specs = map_profile(profile);
+        let profile_specs = map_profile(profile)
+            .into_iter()
+            .filter(|(codec, ..)| codecs.is_none_or(|it| it.contains(codec)))
+            .collect::<Vec<_>>();
         if profile_specs.is_empty() {
             continue;
         }
@@ -83,6 +91,13 @@ pub fn get_capabilities(display: &DrmDisplay) -> Result<Vec<CodecDetails>, VaErr
                         color_depth: profile_specs.3,
                         max_width,
                         max_height,
+                        min_width: 0,
+                        min_height: 0,
+                        max_mb_count: 0,
+                        output_formats: OutputFormats::default(),
+                        histogram_supported: ThreeValue::Unknown,
+                        counter_bit_depth: 0,
+                        max_histogram_bins: 0,
                     })
             });
         }
@@ -114,6 +129,7 @@ pub fn get_capabilities(display: &DrmDisplay) -> Result<Vec<CodecDetails>, VaErr
                         max_width,
                         max_height,
                         b_frames_supported: ThreeValue::Unknown,
+                        profile_guid: [0; 16],
                     })
             });
         }
@@ -405,7 +421,7 @@ mod tests {
         println!("Found device: {}", &drm_display.vendor);
         dbg!(&drm_display);
 
-        let capabilities = get_capabilities(&drm_display)?;
+        let capabilities = get_capabilities(&drm_display, None)?;
 
         dbg!(&capabilities);
         assert!(!capabilities.is_empty());
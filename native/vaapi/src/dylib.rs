@@ -36,8 +36,8 @@ lazy_static! {
     pub static ref LIBVA_DRM: Result<&'static Library, &'static Error> = _LIBVA_DRM_RAW.as_ref();
 }
 
-#[cfg(test)]
-pub(crate) fn is_va_loaded() -> bool {
+/// Returns whether all VA-API libraries could be loaded.
+pub fn is_va_loaded() -> bool {
     LIBVA.is_ok() && LIBVA_DRM.is_ok()
 }
 
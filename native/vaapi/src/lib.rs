@@ -14,6 +14,7 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+pub use dylib::is_va_loaded;
 pub use error::*;
 
 mod error;